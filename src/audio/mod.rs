@@ -0,0 +1,3 @@
+pub mod offset;
+pub mod samples;
+pub mod time_stretch;
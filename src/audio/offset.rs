@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::models::{osu_map::OsuMap, osu_replay::OsuReplay};
+
+/// File the offsets set by hand are kept in, as `<beatmap hash> <milliseconds>` lines.
+const OFFSET_OVERRIDES_FILE: &str = "offsets.txt";
+
+/// How far stable's audio clock runs ahead of the song it plays.
+const STABLE_AUDIO_LATENCY: f64 = 0.015;
+/// Maps before format version 5 were timed against a clock this far behind the song.
+const EARLY_VERSION_TIMING_OFFSET: f64 = 0.024;
+
+/// Seconds the song is ahead of the play time, so that objects and replay frames line
+/// up with the music like they did when the replay was played in stable.
+pub fn audio_offset(beatmap: &OsuMap) -> f64 {
+    let early_version_offset = if beatmap.format_version < 5 {
+        EARLY_VERSION_TIMING_OFFSET
+    } else {
+        0.0
+    };
+    early_version_offset - STABLE_AUDIO_LATENCY
+}
+
+/// Play time in seconds the replay starts at: the beatmap's lead-in before the song,
+/// or earlier if the replay's first frames are.
+pub fn start_time(beatmap: &OsuMap, replay: &OsuReplay) -> f64 {
+    let mut time = 0;
+    let mut first_frame_time = 0;
+    for frame in &replay.replay_data {
+        time += frame.time;
+        // stable starts with two frames off the playfield, at 0 and where the intro was
+        // skipped to
+        if frame.x == 256.0 && frame.y == -500.0 {
+            continue;
+        }
+        first_frame_time = time;
+        break;
+    }
+    first_frame_time.min(-beatmap.general.audio_lead_in) as f64 / 1000.0
+}
+
+/// Where the offsets set by hand are kept, next to the executable so the working
+/// directory doesn't matter, or in the working directory if it can't be found.
+pub fn offset_overrides_path() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|executable| Some(executable.parent()?.join(OFFSET_OVERRIDES_FILE)))
+        .unwrap_or_else(|| PathBuf::from(OFFSET_OVERRIDES_FILE))
+}
+
+/// Offsets the user set for beatmaps, on top of [`audio_offset`].
+#[derive(Debug, Default)]
+pub struct OffsetOverrides {
+    path: PathBuf,
+    /// Seconds by beatmap hash.
+    offsets: HashMap<String, f64>,
+}
+
+impl OffsetOverrides {
+    /// Reads the overrides at `path`. A missing file has none.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut overrides = OffsetOverrides {
+            path: path.to_path_buf(),
+            offsets: HashMap::new(),
+        };
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(overrides),
+            Err(e) => return Err(e),
+        };
+
+        for line in data.lines() {
+            let Some((hash, offset)) = line.trim().split_once(' ') else {
+                continue;
+            };
+            if let Ok(offset) = offset.trim().parse::<f64>() {
+                overrides.offsets.insert(hash.to_string(), offset / 1000.0);
+            }
+        }
+        Ok(overrides)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, beatmap_hash: &str) -> Option<f64> {
+        self.offsets.get(beatmap_hash).copied()
+    }
+
+    /// Sets the override of a beatmap, or removes it with `None`, and saves them all.
+    pub fn set(&mut self, beatmap_hash: &str, offset: Option<f64>) -> io::Result<()> {
+        match offset {
+            Some(offset) => self.offsets.insert(beatmap_hash.to_string(), offset),
+            None => self.offsets.remove(beatmap_hash),
+        };
+
+        let mut offsets: Vec<_> = self.offsets.iter().collect();
+        offsets.sort_by(|a, b| a.0.cmp(b.0));
+        let data: String = offsets
+            .into_iter()
+            .map(|(hash, offset)| format!("{} {}\n", hash, (offset * 1000.0).round()))
+            .collect();
+        fs::write(&self.path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{judgement::tests::replay, models::osu_replay::ReplayData};
+
+    /// A replay of frames as `(milliseconds since the previous frame, x, y)`.
+    fn replay_with_frames(frames: &[(i64, f32, f32)]) -> OsuReplay {
+        let mut replay = replay(&[]);
+        replay.replay_data = frames
+            .iter()
+            .map(|&(time, x, y)| ReplayData {
+                time,
+                x,
+                y,
+                keys: 0,
+                total_time: 0,
+            })
+            .collect();
+        replay
+    }
+
+    fn beatmap(version: u32, audio_lead_in: i64) -> OsuMap {
+        format!(
+            "osu file format v{}\n\n[General]\nAudioLeadIn: {}\n",
+            version, audio_lead_in
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn offsets_early_versions_further() {
+        assert!((audio_offset(&beatmap(14, 0)) + 0.015).abs() < 1e-9);
+        assert!((audio_offset(&beatmap(4, 0)) - 0.009).abs() < 1e-9);
+    }
+
+    #[test]
+    fn starts_at_the_first_frame_on_the_playfield() {
+        let replay = replay_with_frames(&[
+            (0, 256.0, -500.0),
+            (-1, 256.0, -500.0),
+            (-2999, 100.0, 100.0),
+            (16, 101.0, 100.0),
+        ]);
+        assert_eq!(start_time(&beatmap(14, 2000), &replay), -3.0);
+    }
+
+    #[test]
+    fn starts_at_the_lead_in_when_the_intro_was_skipped() {
+        let replay = replay_with_frames(&[
+            (0, 256.0, -500.0),
+            (8000, 256.0, -500.0),
+            (16, 100.0, 100.0),
+        ]);
+        assert_eq!(start_time(&beatmap(14, 2000), &replay), -2.0);
+        assert_eq!(start_time(&beatmap(14, 0), &replay_with_frames(&[])), 0.0);
+    }
+
+    #[test]
+    fn saves_and_loads_overrides() {
+        let path = std::env::temp_dir().join("rewind-rs-offsets.txt");
+        let _ = fs::remove_file(&path);
+
+        let mut overrides = OffsetOverrides::load(&path).unwrap();
+        assert_eq!(overrides.get("abc"), None);
+        overrides.set("abc", Some(0.025)).unwrap();
+        overrides.set("def", Some(-0.01)).unwrap();
+        overrides.set("ghi", Some(0.1)).unwrap();
+        overrides.set("ghi", None).unwrap();
+
+        let saved = fs::read_to_string(&path);
+        let loaded = OffsetOverrides::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved.unwrap(), "abc 25\ndef -10\n");
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.get("abc"), Some(0.025));
+        assert_eq!(loaded.get("def"), Some(-0.01));
+        assert_eq!(loaded.get("ghi"), None);
+    }
+
+    #[test]
+    fn skips_unreadable_override_lines() {
+        let path = std::env::temp_dir().join("rewind-rs-broken-offsets.txt");
+        fs::write(&path, "abc 12\nbroken\ndef soon\n  ghi  -4  \n").unwrap();
+        let loaded = OffsetOverrides::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.get("abc"), Some(0.012));
+        assert_eq!(loaded.get("def"), None);
+        assert_eq!(loaded.get("ghi"), Some(-0.004));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use rodio::{source::Buffered, Decoder, OutputStreamHandle, Source};
+
+use crate::models::{
+    hitsound::HitSound,
+    osu_map::{HitType, OsuMap},
+};
+
+/// Folder with the default samples, named like osu!'s, e.g. `soft-hitclap.wav`.
+const DEFAULT_SAMPLE_DIRECTORY: &str = "samples";
+const EXTENSIONS: [&str; 3] = ["wav", "ogg", "mp3"];
+
+type SampleSource = Buffered<Decoder<BufReader<File>>>;
+
+/// The decoded samples a beatmap's hit sounds use, from the beatmap's folder, the skin
+/// or the default bank, in that order. Samples that can't be found stay silent.
+pub struct SampleBank {
+    /// By file stem, or full file name for the hit sample filenames.
+    beatmap_samples: HashMap<String, SampleSource>,
+    skin_samples: HashMap<String, SampleSource>,
+    default_samples: HashMap<String, SampleSource>,
+}
+
+impl SampleBank {
+    /// Loads every sample the hit sounds of `beatmap` can play.
+    ///
+    /// A skin only needs to bring the samples it changes, the rest come from the default bank.
+    pub fn load(
+        beatmap: &OsuMap,
+        beatmap_directory: &Path,
+        skin_directory: Option<&Path>,
+        default_directory: &Path,
+    ) -> Self {
+        let mut bank = SampleBank {
+            beatmap_samples: HashMap::new(),
+            skin_samples: HashMap::new(),
+            default_samples: HashMap::new(),
+        };
+
+        for hit_object in &beatmap.hit_objects {
+            let edges = match &hit_object.hit_type {
+                HitType::Slider(slider) => slider.repeat as usize + 1,
+                _ => 1,
+            };
+            for edge in 0..edges {
+                let hit_sound = beatmap.hit_sound(hit_object, edge);
+                if let Some(filename) = &hit_sound.filename {
+                    if !bank.beatmap_samples.contains_key(filename) {
+                        if let Some(source) = load_sample(&beatmap_directory.join(filename)) {
+                            bank.beatmap_samples.insert(filename.clone(), source);
+                        }
+                    }
+                    continue;
+                }
+
+                for sample in &hit_sound.samples {
+                    let stem = sample.beatmap_file_stem();
+                    if sample.is_custom() && !bank.beatmap_samples.contains_key(&stem) {
+                        if let Some(source) = find_sample(beatmap_directory, &stem) {
+                            bank.beatmap_samples.insert(stem, source);
+                        }
+                    }
+                    let stem = sample.default_file_stem();
+                    if let Some(skin_directory) = skin_directory {
+                        if !bank.skin_samples.contains_key(&stem) {
+                            if let Some(source) = find_sample(skin_directory, &stem) {
+                                bank.skin_samples.insert(stem.clone(), source);
+                            }
+                        }
+                    }
+                    if !bank.skin_samples.contains_key(&stem)
+                        && !bank.default_samples.contains_key(&stem)
+                    {
+                        if let Some(source) = find_sample(default_directory, &stem) {
+                            bank.default_samples.insert(stem, source);
+                        }
+                    }
+                }
+            }
+        }
+        bank
+    }
+
+    /// Plays `hit_sound` at its volume scaled by `volume`.
+    pub fn play(&self, handle: &OutputStreamHandle, hit_sound: &HitSound, volume: f64) {
+        let volume = (volume * hit_sound.volume as f64 / 100.0) as f32;
+
+        let sources: Vec<&SampleSource> = match &hit_sound.filename {
+            Some(filename) => self.beatmap_samples.get(filename).into_iter().collect(),
+            None => hit_sound
+                .samples
+                .iter()
+                .filter_map(|sample| {
+                    sample
+                        .is_custom()
+                        .then(|| self.beatmap_samples.get(&sample.beatmap_file_stem()))
+                        .flatten()
+                        .or_else(|| {
+                            let stem = sample.default_file_stem();
+                            self.skin_samples
+                                .get(&stem)
+                                .or_else(|| self.default_samples.get(&stem))
+                        })
+                })
+                .collect(),
+        };
+
+        for source in sources {
+            if let Err(e) = handle.play_raw(source.clone().amplify(volume).convert_samples()) {
+                log::warn!("Failed to play hit sound: {}", e);
+            }
+        }
+    }
+}
+
+/// Where the default samples are, next to the executable so the working directory
+/// doesn't matter, or in the working directory when running from a build folder.
+pub fn default_sample_directory() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|executable| Some(executable.parent()?.join(DEFAULT_SAMPLE_DIRECTORY)))
+        .filter(|directory| directory.is_dir())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SAMPLE_DIRECTORY))
+}
+
+fn find_sample(directory: &Path, stem: &str) -> Option<SampleSource> {
+    EXTENSIONS.iter().find_map(|extension| {
+        let path: PathBuf = directory.join(format!("{}.{}", stem, extension));
+        load_sample(&path)
+    })
+}
+
+fn load_sample(path: &Path) -> Option<SampleSource> {
+    let file = File::open(path).ok()?;
+    Decoder::new(BufReader::new(file))
+        .ok()
+        .map(Source::buffered)
+}
@@ -0,0 +1,51 @@
+use crate::skin::Skin;
+
+use super::object::draw_texture;
+
+/// The cursor grows by this much while a key is held, if the skin wants it to.
+const CURSOR_EXPAND_SCALE: f32 = 1.3;
+/// Seconds per turn of a rotating cursor.
+const CURSOR_ROTATION_PERIOD: f64 = 10.0;
+
+/// Draws the cursor at `position` in osu! pixels with the skin's cursor image, or a dot without one.
+pub fn render_cursor(
+    ui: &mut egui::Ui,
+    skin: &Skin,
+    position: (f32, f32),
+    pressed: bool,
+    play_time: f64,
+    scale: f32,
+    offset: egui::Vec2,
+) {
+    let center = egui::Pos2::new(position.0 * scale + offset.x, position.1 * scale + offset.y);
+    let Some(cursor) = &skin.cursor else {
+        ui.painter()
+            .circle_filled(center, 4.0, egui::Color32::from_rgb(255, 255, 0));
+        return;
+    };
+
+    let cursor_scale = if pressed && skin.ini.cursor_expand {
+        scale * CURSOR_EXPAND_SCALE
+    } else {
+        scale
+    };
+    // without CursorCentre the image hangs from its top left corner
+    let center = if skin.ini.cursor_centre {
+        center
+    } else {
+        center + cursor.size * cursor_scale / 2.0
+    };
+    let rotation = if skin.ini.cursor_rotate {
+        (play_time / CURSOR_ROTATION_PERIOD).fract() * std::f64::consts::TAU
+    } else {
+        0.0
+    };
+    draw_texture(
+        ui,
+        cursor,
+        center,
+        cursor_scale,
+        egui::Color32::WHITE,
+        rotation as f32,
+    );
+}
@@ -1,3 +1,4 @@
+pub mod cursor;
 pub mod object;
 
 use std::sync::Arc;
@@ -32,37 +33,43 @@ impl<'a> State<'a> {
             ..Default::default()
         });
 
-        let surface = instance.create_surface(window.clone()).expect("Failed to create surface");
+        let surface = instance
+            .create_surface(window.clone())
+            .expect("Failed to create surface");
 
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
-            },
-        ).await.expect("Failed to find an appropriate adapter");
-
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::default(),
-                label: None,
-            },
-            None,
-        ).await.expect("Failed to create device");
+            })
+            .await
+            .expect("Failed to find an appropriate adapter");
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::default(),
+                    label: None,
+                },
+                None,
+            )
+            .await
+            .expect("Failed to create device");
 
         let surface_capabilities = surface.get_capabilities(&adapter);
-        let surface_format = surface_capabilities.formats.iter()
-            .find(|format| {
-                format.is_srgb()
-            })
+        let surface_format = surface_capabilities
+            .formats
+            .iter()
+            .find(|format| format.is_srgb())
             .copied()
             .unwrap_or(surface_capabilities.formats[0]);
 
-        let present_mode = surface_capabilities.present_modes.iter()
-            .find(|mode| {
-                **mode == wgpu::PresentMode::Fifo
-            })
+        let present_mode = surface_capabilities
+            .present_modes
+            .iter()
+            .find(|mode| **mode == wgpu::PresentMode::Fifo)
             .copied()
             .unwrap_or(surface_capabilities.present_modes[0]);
 
@@ -84,18 +91,14 @@ impl<'a> State<'a> {
                 egui::ViewportId::ROOT,
                 window.as_ref(),
                 None,
-                None
+                None,
             );
-            egui_winit_state.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+            egui_winit_state
+                .set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
             egui_winit_state
         };
 
-        let egui_renderer = Renderer::new(
-            &device,
-            config.format,
-            None,
-            1
-        );
+        let egui_renderer = Renderer::new(&device, config.format, None, 1);
 
         Self {
             surface,
@@ -123,15 +126,16 @@ impl<'a> State<'a> {
         }
     }
 
-    pub fn update(&mut self) {
-    }
+    pub fn update(&mut self) {}
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
-        
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
 
         let gui_buffers = {
             let raw_input = self.egui_winit_state.take_egui_input(self.window.as_ref());
@@ -139,8 +143,9 @@ impl<'a> State<'a> {
                 // Draw your UI here
                 self.gui.render(context)
             });
-            self.egui_winit_state.handle_platform_output(self.window.as_ref(), full_output.platform_output);
-                
+            self.egui_winit_state
+                .handle_platform_output(self.window.as_ref(), full_output.platform_output);
+
             let clipped_primitives = self
                 .egui_winit_state
                 .egui_ctx()
@@ -148,16 +153,12 @@ impl<'a> State<'a> {
 
             let screen_descriptors = egui_wgpu::ScreenDescriptor {
                 size_in_pixels: self.size.into(),
-                pixels_per_point: full_output.pixels_per_point
+                pixels_per_point: full_output.pixels_per_point,
             };
 
             for (id, image_delta) in &full_output.textures_delta.set {
-                self.egui_renderer.update_texture(
-                    &self.device,
-                    &self.queue,
-                    *id,
-                    image_delta,
-                );
+                self.egui_renderer
+                    .update_texture(&self.device, &self.queue, *id, image_delta);
             }
 
             let command_buffers = self.egui_renderer.update_buffers(
@@ -165,12 +166,13 @@ impl<'a> State<'a> {
                 &self.queue,
                 &mut encoder,
                 &clipped_primitives,
-                &screen_descriptors
+                &screen_descriptors,
             );
 
-
             {
-                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
 
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: Some("egui Render Pass"),
@@ -190,7 +192,7 @@ impl<'a> State<'a> {
                 self.egui_renderer.render(
                     &mut render_pass,
                     &clipped_primitives,
-                    &screen_descriptors, 
+                    &screen_descriptors,
                 );
             }
 
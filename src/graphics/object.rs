@@ -1,62 +1,575 @@
-use crate::models::osu_map::{ApproachRate, HitObject, HitType};
+use crate::{
+    models::{
+        mods::Mods,
+        osu_map::{ApproachRate, HitObject, HitType, Slider, PLAYFIELD_CENTER},
+        spin::SpinProgress,
+    },
+    skin::{Skin, SkinTexture},
+};
+
+const SPINNER_RADIUS: f32 = 180.0;
+/// With HD objects fade in over this share of the preempt...
+const HIDDEN_FADE_IN: f64 = 0.4;
+/// ...and right back out over this share.
+const HIDDEN_FADE_OUT: f64 = 0.3;
+const FLASHLIGHT_RADIUS: f32 = 180.0;
+/// Hit circle images are made for a circle with this radius.
+const SKIN_CIRCLE_RADIUS: f32 = 64.0;
+/// Numbers are drawn smaller than the circle they're on.
+const SKIN_NUMBER_SCALE: f32 = 0.8;
 
 pub trait Renderable {
-    fn render(&self, ctx: &mut egui::Ui, beatmap: &crate::models::osu_map::OsuMap, play_time: f64, scale: f32, offset: egui::Vec2);
+    fn render(
+        &self,
+        ctx: &mut egui::Ui,
+        beatmap: &crate::models::osu_map::OsuMap,
+        skin: &Skin,
+        play_time: f64,
+        scale: f32,
+        offset: egui::Vec2,
+    );
 }
 
 impl Renderable for HitObject {
-    fn render(&self, ctx: &mut egui::Ui, beatmap: &crate::models::osu_map::OsuMap, play_time: f64, scale: f32, offset: egui::Vec2) {
+    fn render(
+        &self,
+        ctx: &mut egui::Ui,
+        beatmap: &crate::models::osu_map::OsuMap,
+        skin: &Skin,
+        play_time: f64,
+        scale: f32,
+        offset: egui::Vec2,
+    ) {
         match self.hit_type {
-            HitType::Circle => render_circle(self, ctx, beatmap, play_time, scale, offset),
-            HitType::Slider(_) => render_slider(self, ctx, beatmap, play_time, scale, offset),
-            _ => {}
+            HitType::Circle => render_circle(self, ctx, beatmap, skin, play_time, scale, offset),
+            HitType::Slider(_) => render_slider(self, ctx, beatmap, skin, play_time, scale, offset),
+            HitType::Spinner(_) => {
+                render_spinner(self, ctx, beatmap, skin, play_time, scale, offset)
+            }
         }
     }
 }
 
-fn render_circle(hit_object: &HitObject, ui: &mut egui::Ui, beatmap: &crate::models::osu_map::OsuMap, play_time: f64, scale: f32, offset: egui::Vec2) {
+fn render_circle(
+    hit_object: &HitObject,
+    ui: &mut egui::Ui,
+    beatmap: &crate::models::osu_map::OsuMap,
+    skin: &Skin,
+    play_time: f64,
+    scale: f32,
+    offset: egui::Vec2,
+) {
     let time = hit_object.time as f64 / 1000.0;
-    let ApproachRate {preempt, fade_in, ..} = beatmap.difficulty.approach_rate;
+    let ApproachRate { preempt, .. } = beatmap.difficulty.approach_rate;
 
     let time_to_hit = time - play_time;
-    let opacity = if time <= play_time + fade_in {
-        255
-    } else if time <= play_time + preempt {
-        let time = time - (play_time + fade_in);
-        let opacity = time / (preempt - fade_in);
-        let opacity = 255.0 - opacity * 255.0;
-
-        if opacity < 0.0 {
-            0
-        } else {
-            opacity as u8
-        }
-    } else { 0 };
+    let opacity = object_opacity(hit_object, beatmap, play_time);
 
     let color = egui::Color32::from_white_alpha(opacity);
-    let x = hit_object.x as f32;
-    let y = hit_object.y as f32;
-    let size = (54.4 - 4.48 * beatmap.difficulty.circle_size) as f32 * scale;
+    let (r, g, b) = skin.combo_colour(beatmap, hit_object);
+    let combo_color = egui::Color32::from_rgb(r, g, b).gamma_multiply(opacity as f32 / 255.0);
+    let (x, y) = hit_object.position();
+    let center = egui::Pos2::new(x as f32 * scale + offset.x, y as f32 * scale + offset.y);
+    let size = beatmap.difficulty.circle_radius() as f32 * scale;
+    let sprite_scale = size / SKIN_CIRCLE_RADIUS;
 
     // hit circle
-    ui.painter().circle(
-        egui::Pos2::new(x * scale + offset.x, y * scale + offset.y),
-        size,
-        egui::Color32::from_white_alpha(0),
-        egui::Stroke::new(3.0, color),
+    match &skin.hit_circle {
+        Some(hit_circle) => {
+            draw_texture(ui, hit_circle, center, sprite_scale, combo_color, 0.0);
+            if let Some(overlay) = &skin.hit_circle_overlay {
+                draw_texture(ui, overlay, center, sprite_scale, color, 0.0);
+            }
+        }
+        None => {
+            ui.painter()
+                .circle(center, size, combo_color, egui::Stroke::new(3.0, color));
+        }
+    }
+    render_combo_number(
+        hit_object.combo_number,
+        ui,
+        skin,
+        center,
+        sprite_scale,
+        color,
     );
 
+    // HD hides approach circles
+    if beatmap.mods.contains(Mods::HIDDEN) {
+        return;
+    }
+
     let approach_size_multiplier = 1.0 + 3.0 * (1.0 - (preempt - time_to_hit) / preempt);
 
     // approach circle
-    ui.painter().circle(
-        egui::Pos2::new(x * scale + offset.x, y * scale + offset.y),
-        size * approach_size_multiplier as f32,
-        egui::Color32::from_white_alpha(0),
-        egui::Stroke::new(1.0, color),
+    match &skin.approach_circle {
+        Some(approach_circle) => draw_texture(
+            ui,
+            approach_circle,
+            center,
+            sprite_scale * approach_size_multiplier as f32,
+            combo_color,
+            0.0,
+        ),
+        None => {
+            ui.painter().circle(
+                center,
+                size * approach_size_multiplier as f32,
+                egui::Color32::from_white_alpha(0),
+                egui::Stroke::new(1.0, color),
+            );
+        }
+    }
+}
+
+/// Draws the combo number with the skin's number images, spaced by its HitCircleOverlap.
+fn render_combo_number(
+    number: u32,
+    ui: &mut egui::Ui,
+    skin: &Skin,
+    center: egui::Pos2,
+    sprite_scale: f32,
+    color: egui::Color32,
+) {
+    if skin.numbers.is_empty() {
+        ui.painter().text(
+            center,
+            egui::Align2::CENTER_CENTER,
+            number.to_string(),
+            egui::FontId::proportional(SKIN_CIRCLE_RADIUS * sprite_scale * SKIN_NUMBER_SCALE),
+            color,
+        );
+        return;
+    }
+
+    let scale = sprite_scale * SKIN_NUMBER_SCALE;
+    let digits: Vec<&SkinTexture> = number
+        .to_string()
+        .bytes()
+        .map(|digit| &skin.numbers[(digit - b'0') as usize])
+        .collect();
+    let overlap = skin.ini.hit_circle_overlap as f32;
+    let width: f32 =
+        digits.iter().map(|digit| digit.size.x).sum::<f32>() - overlap * (digits.len() - 1) as f32;
+
+    let mut x = center.x - width * scale / 2.0;
+    for digit in digits {
+        let digit_center = egui::Pos2::new(x + digit.size.x * scale / 2.0, center.y);
+        draw_texture(ui, digit, digit_center, scale, color, 0.0);
+        x += (digit.size.x - overlap) * scale;
+    }
+}
+
+/// Draws `texture` centred on `center`, `scale` screen pixels per pixel of the image at 1x.
+pub(crate) fn draw_texture(
+    ui: &mut egui::Ui,
+    texture: &SkinTexture,
+    center: egui::Pos2,
+    scale: f32,
+    tint: egui::Color32,
+    rotation: f32,
+) {
+    let rect = egui::Rect::from_center_size(center, texture.size * scale);
+    egui::Image::new((texture.handle.id(), rect.size()))
+        .tint(tint)
+        .rotate(rotation, egui::Vec2::splat(0.5))
+        .paint_at(ui, rect);
+}
+
+fn render_slider(
+    hit_object: &HitObject,
+    ui: &mut egui::Ui,
+    beatmap: &crate::models::osu_map::OsuMap,
+    skin: &Skin,
+    play_time: f64,
+    scale: f32,
+    offset: egui::Vec2,
+) {
+    let HitType::Slider(slider) = &hit_object.hit_type else {
+        return;
+    };
+
+    let time = hit_object.time as f64 / 1000.0;
+    let end_time = hit_object.end_time() / 1000.0;
+    let elapsed = (play_time - time) * 1000.0;
+    let opacity = object_opacity(hit_object, beatmap, play_time);
+    // with HD the body fades out over the whole slider instead
+    let body_opacity = if beatmap.mods.contains(Mods::HIDDEN) {
+        hidden_opacity(hit_object, beatmap, play_time, end_time)
+    } else {
+        opacity
+    };
+    let radius = beatmap.difficulty.circle_radius() as f32 * scale;
+    // the path is stored unstacked
+    let (stack_x, stack_y) = hit_object.stack_offset();
+    let to_screen = |(x, y): (f64, f64)| {
+        egui::Pos2::new(
+            (x + stack_x) as f32 * scale + offset.x,
+            (y + stack_y) as f32 * scale + offset.y,
+        )
+    };
+
+    // body with border
+    let points: Vec<egui::Pos2> = slider.path.points.iter().copied().map(to_screen).collect();
+    let border_color = match skin.slider_border(beatmap) {
+        Some((r, g, b)) => {
+            egui::Color32::from_rgb(r, g, b).gamma_multiply(body_opacity as f32 / 255.0)
+        }
+        None => egui::Color32::from_white_alpha(body_opacity),
+    };
+    // the track takes the combo colour, darkened so the head stands out
+    let (r, g, b) = skin
+        .slider_track_override(beatmap)
+        .unwrap_or_else(|| skin.combo_colour(beatmap, hit_object));
+    let body_color =
+        egui::Color32::from_rgb(r / 3, g / 3, b / 3).gamma_multiply(body_opacity as f32 / 255.0);
+    for (width, color) in [(radius, border_color), (radius * 0.85, body_color)] {
+        ui.painter().add(egui::Shape::line(
+            points.clone(),
+            egui::Stroke::new(width * 2.0, color),
+        ));
+        // round caps
+        for point in [points.first(), points.last()].into_iter().flatten() {
+            ui.painter().circle_filled(*point, width, color);
+        }
+    }
+
+    let span = if slider.span_duration > 0.0 {
+        ((elapsed / slider.span_duration).max(0.0) as u32).min(slider.repeat.saturating_sub(1))
+    } else {
+        0
+    };
+
+    // ticks of the current span that haven't been passed yet
+    for (tick_time, distance) in slider.span_ticks(span) {
+        if tick_time > elapsed {
+            let position = to_screen(slider.path.position_at_distance(distance));
+            ui.painter()
+                .circle_filled(position, 3.0 * scale, border_color);
+        }
+    }
+
+    // slider end
+    let end_position = to_screen(slider.path.end_position());
+    ui.painter()
+        .circle_stroke(end_position, radius, egui::Stroke::new(3.0, border_color));
+
+    render_reverse_arrows(slider, span, ui, border_color, to_screen);
+
+    if play_time < time {
+        render_circle(hit_object, ui, beatmap, skin, play_time, scale, offset);
+    } else if play_time <= end_time {
+        // slider ball and follow circle
+        let ball = to_screen(slider.position_at(elapsed));
+        let sprite_scale = radius / SKIN_CIRCLE_RADIUS;
+        match &skin.slider_ball {
+            Some(slider_ball) => draw_texture(
+                ui,
+                slider_ball,
+                ball,
+                sprite_scale,
+                egui::Color32::WHITE,
+                0.0,
+            ),
+            None => {
+                ui.painter().circle_filled(
+                    ball,
+                    radius * 0.85,
+                    egui::Color32::from_white_alpha(180),
+                );
+            }
+        }
+        match &skin.slider_follow_circle {
+            Some(follow_circle) => draw_texture(
+                ui,
+                follow_circle,
+                ball,
+                sprite_scale,
+                egui::Color32::WHITE,
+                0.0,
+            ),
+            None => {
+                ui.painter().circle_stroke(
+                    ball,
+                    radius * 2.4,
+                    egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 170, 0)),
+                );
+            }
+        }
+    }
+}
+
+fn render_reverse_arrows(
+    slider: &Slider,
+    span: u32,
+    ui: &mut egui::Ui,
+    color: egui::Color32,
+    to_screen: impl Fn((f64, f64)) -> egui::Pos2,
+) {
+    let remaining_repeats = slider.repeat.saturating_sub(span + 1);
+    if remaining_repeats == 0 {
+        return;
+    }
+
+    let length = slider.path.length();
+    // the arrow at the end the ball is heading to, plus the one at the other end if the ball comes back there
+    let heading_to_end = span % 2 == 0;
+    let mut arrows = vec![heading_to_end];
+    if remaining_repeats > 1 {
+        arrows.push(!heading_to_end);
+    }
+
+    for at_end in arrows {
+        let (tip, back) = if at_end {
+            (length, length - 10.0)
+        } else {
+            (0.0, 10.0)
+        };
+        let center = to_screen(slider.path.position_at_distance(tip));
+        let towards = to_screen(slider.path.position_at_distance(back.clamp(0.0, length)));
+        let direction = (towards - center).normalized();
+        let normal = egui::Vec2::new(-direction.y, direction.x);
+        let size = 12.0;
+        let stroke = egui::Stroke::new(3.0, color);
+        let arrow_tip = center + direction * size;
+        ui.painter().line_segment(
+            [arrow_tip, arrow_tip - direction * size + normal * size],
+            stroke,
+        );
+        ui.painter().line_segment(
+            [arrow_tip, arrow_tip - direction * size - normal * size],
+            stroke,
+        );
+    }
+}
+
+fn render_spinner(
+    hit_object: &HitObject,
+    ui: &mut egui::Ui,
+    beatmap: &crate::models::osu_map::OsuMap,
+    skin: &Skin,
+    play_time: f64,
+    scale: f32,
+    offset: egui::Vec2,
+) {
+    let time = hit_object.time as f64 / 1000.0;
+    let end_time = hit_object.end_time() / 1000.0;
+    if play_time > end_time {
+        return;
+    }
+
+    let color = egui::Color32::from_white_alpha(fade_in_opacity(hit_object, beatmap, play_time));
+    let center = egui::Pos2::new(
+        PLAYFIELD_CENTER.0 as f32 * scale + offset.x,
+        PLAYFIELD_CENTER.1 as f32 * scale + offset.y,
+    );
+    let radius = SPINNER_RADIUS * scale;
+
+    // spinner images are scaled to the spinner's size rather than drawn at their own
+    match &skin.spinner_circle {
+        Some(spinner_circle) => draw_texture(
+            ui,
+            spinner_circle,
+            center,
+            radius * 2.0 / spinner_circle.size.x,
+            color,
+            0.0,
+        ),
+        None => {
+            ui.painter()
+                .circle_stroke(center, radius, egui::Stroke::new(3.0, color));
+            ui.painter().circle_filled(center, 4.0 * scale, color);
+        }
+    }
+
+    // the approach circle shrinks over the whole spinner
+    if play_time >= time && end_time > time {
+        let remaining = ((end_time - play_time) / (end_time - time)) as f32;
+        match &skin.spinner_approach_circle {
+            Some(approach_circle) => draw_texture(
+                ui,
+                approach_circle,
+                center,
+                radius * 2.0 * remaining / approach_circle.size.x,
+                color,
+                0.0,
+            ),
+            None => {
+                ui.painter().circle_stroke(
+                    center,
+                    radius * remaining,
+                    egui::Stroke::new(1.0, color),
+                );
+            }
+        }
+    }
+}
+
+/// Draws how far the player got spinning: the rotation, a progress ring, the RPM and clear/bonus state.
+pub fn render_spin_progress(
+    progress: &SpinProgress,
+    ui: &mut egui::Ui,
+    scale: f32,
+    offset: egui::Vec2,
+) {
+    let center = egui::Pos2::new(
+        PLAYFIELD_CENTER.0 as f32 * scale + offset.x,
+        PLAYFIELD_CENTER.1 as f32 * scale + offset.y,
+    );
+    let radius = SPINNER_RADIUS * scale;
+    let color = if progress.is_cleared() {
+        egui::Color32::from_rgb(0, 220, 120)
+    } else {
+        egui::Color32::from_rgb(255, 170, 0)
+    };
+
+    // progress ring, clockwise from the top
+    let completion = progress.completion();
+    let segments = (64.0 * completion).ceil() as usize;
+    let ring: Vec<egui::Pos2> = (0..=segments)
+        .map(|segment| {
+            let angle = -std::f64::consts::FRAC_PI_2
+                + 2.0 * std::f64::consts::PI * completion * segment as f64 / segments.max(1) as f64;
+            center + egui::Vec2::new(angle.cos() as f32, angle.sin() as f32) * (radius + 6.0)
+        })
+        .collect();
+    ui.painter()
+        .add(egui::Shape::line(ring, egui::Stroke::new(4.0, color)));
+
+    // spinner arm following the accumulated rotation
+    let arm = egui::Vec2::new(
+        progress.rotation.cos() as f32,
+        progress.rotation.sin() as f32,
+    ) * radius
+        * 0.8;
+    ui.painter()
+        .line_segment([center, center + arm], egui::Stroke::new(2.0, color));
+
+    let mut status = format!(
+        "{:.0} RPM\n{:.1} / {} spins",
+        progress.rpm,
+        progress.spins(),
+        progress.required_spins
+    );
+    if progress.is_cleared() {
+        status.push_str("\nCLEAR");
+        if progress.bonus_spins() > 0 {
+            status.push_str(&format!(" +{} bonus", progress.bonus_spins()));
+        }
+    }
+    ui.painter().text(
+        center + egui::Vec2::new(0.0, radius * 0.5),
+        egui::Align2::CENTER_CENTER,
+        status,
+        egui::FontId::default(),
+        color,
+    );
+}
+
+fn object_opacity(
+    hit_object: &HitObject,
+    beatmap: &crate::models::osu_map::OsuMap,
+    play_time: f64,
+) -> u8 {
+    if beatmap.mods.contains(Mods::HIDDEN) {
+        let time = hit_object.time as f64 / 1000.0;
+        let preempt = beatmap.difficulty.approach_rate.preempt;
+        let fade_out_end = time - preempt * (1.0 - HIDDEN_FADE_IN - HIDDEN_FADE_OUT);
+        hidden_opacity(hit_object, beatmap, play_time, fade_out_end)
+    } else {
+        fade_in_opacity(hit_object, beatmap, play_time)
+    }
+}
+
+/// Opacity with HD, fading in from the start of the preempt and out until `fade_out_end` in seconds.
+fn hidden_opacity(
+    hit_object: &HitObject,
+    beatmap: &crate::models::osu_map::OsuMap,
+    play_time: f64,
+    fade_out_end: f64,
+) -> u8 {
+    let time = hit_object.time as f64 / 1000.0;
+    let preempt = beatmap.difficulty.approach_rate.preempt;
+    let fade_in_start = time - preempt;
+    let fade_out_start = fade_in_start + preempt * HIDDEN_FADE_IN;
+
+    let opacity = if play_time < fade_out_start {
+        (play_time - fade_in_start) / (preempt * HIDDEN_FADE_IN)
+    } else if fade_out_end > fade_out_start {
+        1.0 - (play_time - fade_out_start) / (fade_out_end - fade_out_start)
+    } else {
+        0.0
+    };
+    (opacity.clamp(0.0, 1.0) * 255.0) as u8
+}
+
+/// Darkens the playfield outside of the area Flashlight lets the player see around the cursor.
+/// The area shrinks at 100 and 200 combo like in osu!stable.
+pub fn render_flashlight(
+    ui: &mut egui::Ui,
+    cursor: (f32, f32),
+    combo: u32,
+    scale: f32,
+    offset: egui::Vec2,
+) {
+    let size_multiplier = if combo >= 200 {
+        0.625
+    } else if combo >= 100 {
+        0.8125
+    } else {
+        1.0
+    };
+    let radius = FLASHLIGHT_RADIUS * size_multiplier * scale;
+    let playfield =
+        egui::Rect::from_min_size(offset.to_pos2(), egui::Vec2::new(512.0, 384.0) * scale);
+    let painter = ui.painter().with_clip_rect(playfield);
+    let center = egui::Pos2::new(cursor.0 * scale + offset.x, cursor.1 * scale + offset.y);
+
+    // a ring wide enough to cover the playfield from anywhere, with a soft inner edge
+    let cover = playfield.size().length() * 2.0;
+    painter.circle_stroke(
+        center,
+        radius + cover / 2.0,
+        egui::Stroke::new(cover, egui::Color32::from_black_alpha(240)),
     );
+    let edge = 20.0 * scale;
+    for step in 0..8 {
+        let fraction = step as f32 / 8.0;
+        painter.circle_stroke(
+            center,
+            radius - edge * fraction,
+            egui::Stroke::new(
+                edge / 8.0,
+                egui::Color32::from_black_alpha((240.0 * (1.0 - fraction)) as u8),
+            ),
+        );
+    }
 }
 
-fn render_slider(hit_object: &HitObject, ui: &mut egui::Ui, beatmap: &crate::models::osu_map::OsuMap, play_time: f64, scale: f32, offset: egui::Vec2) {
-    render_circle(hit_object, ui, beatmap, play_time, scale, offset);
+fn fade_in_opacity(
+    hit_object: &HitObject,
+    beatmap: &crate::models::osu_map::OsuMap,
+    play_time: f64,
+) -> u8 {
+    let time = hit_object.time as f64 / 1000.0;
+    let ApproachRate {
+        preempt, fade_in, ..
+    } = beatmap.difficulty.approach_rate;
+
+    if time <= play_time + fade_in {
+        255
+    } else if time <= play_time + preempt {
+        let time = time - (play_time + fade_in);
+        let opacity = time / (preempt - fade_in);
+        let opacity = 255.0 - opacity * 255.0;
+
+        if opacity < 0.0 {
+            0
+        } else {
+            opacity as u8
+        }
+    } else {
+        0
+    }
 }
@@ -0,0 +1,272 @@
+use std::time::Instant;
+
+/// How far the clock may drift from the audio before it jumps to it instead of catching up.
+const MAX_AUDIO_DRIFT: f64 = 0.05;
+/// Share of the drift from the audio corrected each time the audio position is read.
+const AUDIO_CATCH_UP: f64 = 0.1;
+
+/// Real time for a [`PlaybackClock`] to run on, in seconds from any fixed point.
+pub trait TimeSource {
+    fn now(&self) -> f64;
+}
+
+/// Wall clock time that never goes backwards.
+pub struct MonotonicTime {
+    start: Instant,
+}
+
+impl MonotonicTime {
+    pub fn new() -> Self {
+        MonotonicTime {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for MonotonicTime {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+/// The play time of a replay in seconds of map time.
+///
+/// It runs on its time source at `rate` map seconds per real second. While the song
+/// plays, [`PlaybackClock::follow_audio`] keeps it on the song's position, so the
+/// cursor stays in sync with the music however the frames are paced.
+pub struct PlaybackClock<T: TimeSource = MonotonicTime> {
+    source: T,
+    playing: bool,
+    rate: f64,
+    /// Play time at `anchor`.
+    position: f64,
+    /// Source time the clock last started, seeked or changed rate at.
+    anchor: f64,
+    /// Play time the song was at when its position was zero, if it's playing.
+    audio_start: Option<f64>,
+}
+
+impl<T: TimeSource> PlaybackClock<T> {
+    pub fn new(source: T, rate: f64) -> Self {
+        let anchor = source.now();
+        PlaybackClock {
+            source,
+            playing: false,
+            rate,
+            position: 0.0,
+            anchor,
+            audio_start: None,
+        }
+    }
+
+    pub fn time(&self) -> f64 {
+        if self.playing {
+            self.position + (self.source.now() - self.anchor) * self.rate
+        } else {
+            self.position
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn play(&mut self) {
+        if !self.playing {
+            self.anchor = self.source.now();
+            self.playing = true;
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.position = self.time();
+        self.playing = false;
+        self.audio_start = None;
+    }
+
+    pub fn seek(&mut self, time: f64) {
+        self.reanchor(time);
+        self.audio_start = None;
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        self.reanchor(self.time());
+        self.rate = rate;
+        self.audio_start = None;
+    }
+
+    /// Marks the song as restarted from the current time, with its position at zero.
+    pub fn start_audio(&mut self) {
+        self.audio_start = Some(self.time());
+    }
+
+    /// Moves the clock towards the song's position, which is how much real time of
+    /// the song has played since [`PlaybackClock::start_audio`].
+    ///
+    /// Small drift is caught up over a few frames so the cursor doesn't stutter with
+    /// the coarse audio position, large drift is jumped over.
+    pub fn follow_audio(&mut self, audio_position: f64) {
+        let Some(audio_start) = self.audio_start else {
+            return;
+        };
+        // the position only starts counting once the output has read from the song
+        if !self.playing || audio_position <= 0.0 {
+            return;
+        }
+
+        let time = self.time();
+        let drift = audio_start + audio_position * self.rate - time;
+        if drift.abs() > MAX_AUDIO_DRIFT {
+            self.reanchor(time + drift);
+        } else {
+            self.reanchor(time + drift * AUDIO_CATCH_UP);
+        }
+    }
+
+    fn reanchor(&mut self, time: f64) {
+        self.position = time;
+        self.anchor = self.source.now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    /// Time that only moves when told to, so playback can be stepped exactly.
+    /// Clones share the same time.
+    #[derive(Debug, Default, Clone)]
+    pub struct ManualTime {
+        now: Rc<Cell<f64>>,
+    }
+
+    impl ManualTime {
+        pub fn advance(&self, seconds: f64) {
+            self.now.set(self.now.get() + seconds);
+        }
+    }
+
+    impl TimeSource for ManualTime {
+        fn now(&self) -> f64 {
+            self.now.get()
+        }
+    }
+
+    fn clock(rate: f64) -> (ManualTime, PlaybackClock<ManualTime>) {
+        let time = ManualTime::default();
+        (time.clone(), PlaybackClock::new(time, rate))
+    }
+
+    fn assert_time(clock: &PlaybackClock<ManualTime>, expected: f64) {
+        assert!(
+            (clock.time() - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            clock.time()
+        );
+    }
+
+    #[test]
+    fn runs_only_while_playing() {
+        let (time, mut clock) = clock(1.5);
+        time.advance(1.0);
+        assert_time(&clock, 0.0);
+
+        clock.play();
+        time.advance(1.0);
+        assert_time(&clock, 1.5);
+
+        clock.pause();
+        assert!(!clock.is_playing());
+        time.advance(1.0);
+        assert_time(&clock, 1.5);
+
+        clock.play();
+        time.advance(2.0);
+        assert_time(&clock, 4.5);
+    }
+
+    #[test]
+    fn seeks_and_changes_rate() {
+        let (time, mut clock) = clock(1.0);
+        clock.seek(10.0);
+        assert_time(&clock, 10.0);
+
+        clock.play();
+        time.advance(1.0);
+        assert_time(&clock, 11.0);
+
+        // the time so far is kept, only what follows runs faster
+        clock.set_rate(2.0);
+        assert_time(&clock, 11.0);
+        time.advance(1.0);
+        assert_time(&clock, 13.0);
+
+        clock.seek(-2.0);
+        assert_time(&clock, -2.0);
+        time.advance(0.5);
+        assert_time(&clock, -1.0);
+    }
+
+    #[test]
+    fn catches_up_with_small_drift() {
+        let (time, mut clock) = clock(1.0);
+        clock.seek(10.0);
+        clock.play();
+        clock.start_audio();
+        time.advance(1.0);
+
+        // 20 ms behind the song, a tenth of which is caught up
+        clock.follow_audio(1.02);
+        assert_time(&clock, 11.002);
+        time.advance(1.0);
+        assert_time(&clock, 12.002);
+    }
+
+    #[test]
+    fn jumps_over_large_drift() {
+        let (time, mut clock) = clock(2.0);
+        clock.play();
+        clock.start_audio();
+        time.advance(1.0);
+
+        // the song is at 1.5 s of real time, 3 s of map time at double rate
+        clock.follow_audio(1.5);
+        assert_time(&clock, 3.0);
+        time.advance(0.5);
+        assert_time(&clock, 4.0);
+    }
+
+    #[test]
+    fn ignores_audio_it_is_not_following() {
+        let (time, mut clock) = clock(1.0);
+        clock.play();
+        time.advance(1.0);
+        clock.follow_audio(5.0);
+        assert_time(&clock, 1.0);
+
+        // the song hasn't been read from yet
+        clock.start_audio();
+        clock.follow_audio(0.0);
+        assert_time(&clock, 1.0);
+
+        // seeking, changing rate and pausing all restart the song
+        for stop_following in [
+            |clock: &mut PlaybackClock<ManualTime>| clock.seek(1.0),
+            |clock: &mut PlaybackClock<ManualTime>| clock.set_rate(1.0),
+            |clock: &mut PlaybackClock<ManualTime>| clock.pause(),
+        ] {
+            clock.start_audio();
+            stop_following(&mut clock);
+            clock.play();
+            clock.follow_audio(5.0);
+            assert_time(&clock, 1.0);
+        }
+    }
+}
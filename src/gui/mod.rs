@@ -1,21 +1,60 @@
-use std::{env, fs::File, io::BufReader, path::PathBuf, time::SystemTime};
+pub mod clock;
+pub mod section_loop;
+pub mod seek;
+pub mod timeline;
+
+use std::{
+    env,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use egui::Context;
 use rodio::{source::Buffered, Decoder, OutputStream, Sink, Source};
 use winit::window::Window;
 
 use crate::{
-    graphics::object::Renderable,
+    audio::{
+        offset::{self, OffsetOverrides},
+        samples::{self, SampleBank},
+        time_stretch::TimeStretch,
+    },
+    graphics::{
+        cursor::render_cursor,
+        object::{render_flashlight, render_spin_progress, Renderable},
+    },
+    gui::{
+        clock::{MonotonicTime, PlaybackClock},
+        section_loop::SectionLoop,
+        seek::{format_time, parse_time, SeekTarget, SHORTCUTS},
+        timeline::{Timeline, TimelineSeek},
+    },
+    judgement::{
+        self,
+        hp::HpSimulation,
+        score::{self, ScoreReport, ScoreSimulation},
+        slider::CheckpointKind,
+        HitResult, Judgement, JudgementReport,
+    },
     models::{
-        osu_map::{ApproachRate, HitObject, HitType, OverallDifficulty, Spinner},
-        osu_replay::Keys,
+        hitsound::HitSound,
+        mods::Mods,
+        osu_map::{ApproachRate, HitType, OsuMap, OverallDifficulty},
+        osu_replay::{Keys, LifeBarPoint, OsuReplay},
+        spin::SpinProgress,
     },
+    skin::{self, Skin},
 };
 
 pub struct Gui {
     osu_data: Option<OsuData>,
-    system_time: SystemTime,
     replay_data: Option<ReplayPlaybackData>,
+    skin: Skin,
+    /// Folder with the default hit sounds.
+    sample_directory: PathBuf,
+    offset_overrides: OffsetOverrides,
     errors: Vec<String>,
 }
 
@@ -27,61 +66,84 @@ struct OsuData {
 struct ReplayPlaybackData {
     pub(crate) replay: crate::models::osu_replay::OsuReplay,
     pub(crate) beatmap: crate::models::osu_map::OsuMap,
+    judgements: Vec<Judgement>,
+    judgement_report: JudgementReport,
+    score_simulation: ScoreSimulation,
+    score_report: ScoreReport,
+    hp_simulation: HpSimulation,
+    /// `(time in seconds, sound)` of everything the player hit, in time order.
+    hit_sounds: Vec<(f64, HitSound)>,
+    timeline: Timeline,
     replay_path: String,
+    beatmap_hash: String,
     #[allow(dead_code)] // need to store ref
     audio_output: OutputStream,
 
-    offset: f64,
+    /// Seconds the song is ahead of the play time, as derived from the beatmap.
+    audio_offset: f64,
+    /// Seconds the user moved the song on top of `audio_offset`.
+    offset_override: f64,
     playback_status: PlaybackStatus,
 }
 
 struct PlaybackStatus {
+    /// Speed on top of the one the replay's mods play at, before the section loop slows
+    /// it down.
     playback_speed: f64,
-    playing: bool,
+    mods: Mods,
+    clock: PlaybackClock,
+    /// Time of the frame being drawn, read from `clock` once so everything drawn agrees.
     play_time: f64,
     replay_data_index: usize,
     audio_song_source: Buffered<Decoder<BufReader<File>>>,
-    hit_sound_source: Buffered<Decoder<BufReader<File>>>,
+    sample_bank: SampleBank,
     audio_stream_handle: rodio::OutputStreamHandle,
     audio_song_sink: Sink,
 
-    misses: Vec<Miss>,
     volume: f64,
 
     hit_object_index: usize,
-    next_hit_object_to_hit_index: usize,
-    last_hit_object_index: Option<usize>,
-    last_checked_cursor_index: usize,
-    last_missed_hit_object: Option<usize>,
-    last_maybe_missed_hit_object: Option<usize>,
+    /// Play time up to which judgements have played their hit sound or paused on a miss.
+    judged_until: f64,
 
     pause_on_miss: bool,
-}
-
-struct Miss {
-    time: f64,
-    hit_object_index: usize,
-    cursor_position: (f64, f64),
+    /// Text of the go to time box.
+    go_to_time: String,
+    section_loop: SectionLoop,
 }
 
 impl Gui {
     pub fn new() -> Self {
+        let mut errors = Vec::new();
+        let offset_overrides_path = offset::offset_overrides_path();
+        let offset_overrides = OffsetOverrides::load(&offset_overrides_path).unwrap_or_else(|e| {
+            errors.push(format!(
+                "Failed to read offsets from {}.\n{}",
+                offset_overrides_path.display(),
+                e
+            ));
+            OffsetOverrides::default()
+        });
+        let sample_directory = samples::default_sample_directory();
+        if !sample_directory.is_dir() {
+            errors.push(format!(
+                "Default hit sounds not found in {}, only beatmap and skin hit sounds will play.",
+                sample_directory.display()
+            ));
+        }
+
         Self {
             osu_data: None,
-            system_time: SystemTime::now(),
             replay_data: None,
-            errors: Vec::new(),
+            skin: Skin::default(),
+            sample_directory,
+            offset_overrides,
+            errors,
         }
     }
 
     pub fn render(&mut self, context: &Context) {
         egui::CentralPanel::default().show(&context, |ui| {
-            let delta_time = SystemTime::now()
-                .duration_since(self.system_time)
-                .unwrap()
-                .as_secs_f64();
-            self.system_time = SystemTime::now();
-
             ui.heading("Hello, egui!");
             ui.label("This is a simple egui window.");
 
@@ -107,32 +169,30 @@ impl Gui {
 
                         Some(path)
                     }
-                    "linux" => {
-                        let path = PathBuf::from(format!("/home/{}", env::var("USER").unwrap()))
+                    "linux" => env::var("USER").ok().map(|user| {
+                        PathBuf::from(format!("/home/{}", user))
                             .join(".local")
                             .join("share")
-                            .join("osu-wine");
-
-                        Some(path)
-                    }
+                            .join("osu-wine")
+                    }),
                     _ => None,
                 }
                 .map(|path| path.join("osu!").join("osu!.db"));
 
-                println!("{:?}", osu_path);
-                println!("{:?}", env::consts::OS);
-                println!("{:?}", osu_path.clone().unwrap().exists());
-
-                let dialog = rfd::FileDialog::new().set_file_name("osu!.db");
-
-                let osu_db_path = if let Some(osu_path) = osu_path {
-                    if osu_path.exists() {
-                        Some(osu_path)
-                    } else {
-                        dialog.clone().pick_file()
+                let osu_db_path = match osu_path.filter(|path| path.exists()) {
+                    Some(path) => Some(path),
+                    None => {
+                        let picked = rfd::FileDialog::new()
+                            .set_file_name("osu!.db")
+                            .pick_file();
+                        if picked.is_none() {
+                            self.errors.push(
+                                "Couldn't find osu!.db in the default osu! folder, pick it to load your beatmaps."
+                                    .to_string(),
+                            );
+                        }
+                        picked
                     }
-                } else {
-                    dialog.clone().pick_file()
                 };
 
                 if let Some(path) = osu_db_path {
@@ -145,15 +205,36 @@ impl Gui {
                             path, e
                         )),
                         Ok(beatmaps) => {
-                            self.osu_data = Some(OsuData {
-                                path: path.into(),
-                                beatmaps,
-                            })
+                            let path = PathBuf::from(path);
+                            // the skin the player has selected in osu!
+                            if let Some(skin_path) = path.parent().and_then(skin::find_user_skin) {
+                                match Skin::load(ui.ctx(), &skin_path) {
+                                    Ok(skin) => self.skin = skin,
+                                    Err(e) => self.errors.push(e.to_string()),
+                                }
+                            }
+                            self.osu_data = Some(OsuData { path, beatmaps })
                         }
                     }
                 }
             };
 
+            if ui.button("Load skin").clicked() {
+                let mut dialog = rfd::FileDialog::new();
+                if let Some(OsuData { path, .. }) = &self.osu_data {
+                    if let Some(osu_path) = path.parent() {
+                        dialog = dialog.set_directory(osu_path.join("Skins"));
+                    }
+                }
+                if let Some(skin_path) = dialog.pick_folder() {
+                    match Skin::load(ui.ctx(), &skin_path) {
+                        Ok(skin) => self.skin = skin,
+                        Err(e) => self.errors.push(e.to_string()),
+                    }
+                }
+            }
+            ui.label(format!("Skin: {}", self.skin.name()));
+
             if let Some(OsuData { path, beatmaps }) = &self.osu_data {
                 ui.label(format!("osu!.db path: {}", path.display()));
 
@@ -169,77 +250,16 @@ impl Gui {
                         .pick_file()
                     {
                         self.errors.clear();
-                        let replay = crate::models::osu_replay::OsuReplay::from_file(&replay_path);
-
-                        if let Some(beatmap_listing) = beatmaps
-                            .beatmaps
-                            .iter()
-                            .find(|b| b.hash == Some(replay.beatmap_hash.clone()))
-                        {
-                            let (_stream, handle) = OutputStream::try_default().unwrap();
-
-                            let osu_beatmap_path = path
-                                .parent()
-                                .unwrap()
-                                .join("Songs")
-                                .join(beatmap_listing.folder_name.as_ref().unwrap());
-
-                            let audio_path =
-                                osu_beatmap_path.join(beatmap_listing.audio.as_ref().unwrap());
-
-                            let song_source = {
-                                let file = BufReader::new(File::open(audio_path).unwrap());
-                                Decoder::new(file).unwrap().buffered()
-                            };
-                            let sink = Sink::try_new(&handle).unwrap();
-                            sink.pause();
-
-                            let hit_sound_source = {
-                                let file = BufReader::new(File::open("hit.wav").unwrap());
-                                Decoder::new(file).unwrap().buffered()
-                            };
-
-                            let osu_file_path =
-                                osu_beatmap_path.join(beatmap_listing.file_name.as_ref().unwrap());
-
-                            let beatmap = crate::models::osu_map::OsuMap::from_file(&osu_file_path);
-
-                            // How to calculate offset?
-                            let offset = -1.778 + beatmap.hit_objects[0].time as f64 / 1000.0;
-
-                            let status = PlaybackStatus {
-                                playback_speed: 1.0,
-                                playing: false,
-                                play_time: 0.0,
-                                replay_data_index: 0,
-                                audio_song_source: song_source,
-                                hit_sound_source,
-                                audio_stream_handle: handle,
-                                audio_song_sink: sink,
-                                misses: Vec::new(),
-                                hit_object_index: 0,
-                                next_hit_object_to_hit_index: 0,
-                                last_hit_object_index: None,
-                                last_missed_hit_object: None,
-                                last_maybe_missed_hit_object: None,
-                                last_checked_cursor_index: 0,
-                                pause_on_miss: false,
-                                volume: 1.0,
-                            };
-
-                            self.replay_data = Some(ReplayPlaybackData {
-                                replay,
-                                beatmap,
-                                replay_path: replay_path.display().to_string(),
-                                audio_output: _stream,
-                                offset,
-                                playback_status: status,
-                            });
-                        } else {
-                            self.errors.push(format!(
-                                "Failed to find beatmap with hash {}.",
-                                replay.beatmap_hash
-                            ));
+                        match load_replay(
+                            path,
+                            beatmaps,
+                            &replay_path,
+                            self.skin.directory.as_deref(),
+                            &self.sample_directory,
+                            &self.offset_overrides,
+                        ) {
+                            Ok(replay_data) => self.replay_data = Some(replay_data),
+                            Err(e) => self.errors.push(e),
                         }
                     }
                 };
@@ -254,29 +274,104 @@ impl Gui {
                 if let Some(playback) = &mut self.replay_data {
                     let ReplayPlaybackData {
                         ref replay_path,
-                        ref replay,
-                        offset: time_offset,
                         playback_status: ref mut status,
                         ..
                     } = *playback;
 
                     ui.label(format!("Picked path: {}", replay_path));
+                    if ui.button("Save replay as").clicked() {
+                        let path = rfd::FileDialog::new()
+                            .add_filter("osu! replay", &["osr"])
+                            .save_file();
+                        if let Some(path) = path {
+                            if let Err(e) = playback.replay.to_file(&path) {
+                                self.errors.push(format!(
+                                    "Failed to save replay to {}.\n{}",
+                                    path.display(),
+                                    e
+                                ));
+                            }
+                        }
+                    }
 
-                    let audio_offset = if time_offset < 0.0 { 0.0 } else { time_offset };
-                    let replay_offset = if time_offset > 0.0 { 0.0 } else { -time_offset };
+                    let audio_offset = playback.audio_offset + playback.offset_override;
+
+                    ui.label(format!("Beatmap: {}", playback.beatmap.metadata));
+
+                    let mut toggle_play = false;
+                    let mut seek_target = None;
+                    if !ui.ctx().wants_keyboard_input() {
+                        ui.input_mut(|input| {
+                            toggle_play =
+                                input.consume_key(egui::Modifiers::NONE, egui::Key::Space);
+                            seek_target = SHORTCUTS
+                                .iter()
+                                .find(|(modifiers, key, _)| input.consume_key(*modifiers, *key))
+                                .map(|(_, _, target)| *target);
+                        });
+                    }
 
-                    ui.label(format!("Beatmap: {}", replay.beatmap_hash));
-                    if ui.button("Play/Pause").clicked() {
-                        status.playing = !status.playing;
-                        if status.playing {
-                            status.play(audio_offset);
-                        } else {
+                    if ui.button("Play/Pause").clicked() || toggle_play {
+                        if status.clock.is_playing() {
                             status.pause();
+                        } else {
+                            status.play(audio_offset);
                         }
                     };
 
-                    ui.label(format!("Playing: {}", status.playing));
-                    ui.label(format!("Play Time:\t{}", status.play_time));
+                    ui.label(format!("Playing: {}", status.clock.is_playing()));
+                    ui.horizontal(|ui| {
+                        for (label, target) in [
+                            ("Previous miss", SeekTarget::PreviousMiss),
+                            ("Previous object", SeekTarget::PreviousObject),
+                            ("Previous frame", SeekTarget::PreviousFrame),
+                            ("Next frame", SeekTarget::NextFrame),
+                            ("Next object", SeekTarget::NextObject),
+                            ("Next miss", SeekTarget::NextMiss),
+                        ] {
+                            if ui.button(label).clicked() {
+                                seek_target = Some(target);
+                            }
+                        }
+
+                        let response = ui.add(
+                            egui::TextEdit::singleline(&mut status.go_to_time)
+                                .desired_width(80.0)
+                                .hint_text("m:ss.mmm"),
+                        );
+                        let submitted = response.lost_focus()
+                            && ui.input(|input| input.key_pressed(egui::Key::Enter));
+                        if ui.button("Go to time").clicked() || submitted {
+                            match parse_time(&status.go_to_time) {
+                                Some(time) => seek_target = Some(SeekTarget::Time(time)),
+                                None => self.errors.push(format!(
+                                    "Invalid time {:?}, expected milliseconds or m:ss.mmm.",
+                                    status.go_to_time
+                                )),
+                            }
+                        }
+                    });
+                    ui.label(
+                        "Space: play/pause, arrows: 5 s, shift+arrows: object, \
+                         ctrl+arrows: miss, comma/period: frame",
+                    );
+
+                    if let Some(target) = seek_target {
+                        let time = target.resolve(
+                            status.play_time,
+                            &playback.replay,
+                            &playback.beatmap,
+                            &playback.judgements,
+                        );
+                        if let Some(time) = time {
+                            if target.pauses() {
+                                status.pause();
+                            }
+                            status.seek(time, &playback.replay, &playback.beatmap, audio_offset);
+                        }
+                    }
+
+                    ui.label(format!("Play Time:\t{}", format_time(status.play_time)));
                     ui.label(format!(
                         "Audio Time:\t{}",
                         status.audio_song_sink.get_pos().as_secs_f64()
@@ -288,29 +383,150 @@ impl Gui {
                         status.audio_song_sink.set_volume(status.volume as f32);
                     }
 
-                    ui.add(egui::Slider::new(&mut playback.offset, -5.0..=5.0).text("Offset"));
-                    ui.add(
-                        egui::Slider::new(&mut status.playback_speed, 0.1..=2.0)
-                            .text("Playback speed")
-                            .step_by(0.05),
-                    );
-                    ui.checkbox(&mut status.pause_on_miss, "Pause on miss");
-                    ui.label(format!("Offset: {}", time_offset));
-                    ui.label(format!("Audio offset: {}", audio_offset));
-                    ui.label(format!("Replay offset: {}", replay_offset));
+                    ui.horizontal(|ui| {
+                        let mut offset_ms = playback.offset_override * 1000.0;
+                        let response = ui.add(
+                            egui::Slider::new(&mut offset_ms, -300.0..=300.0)
+                                .step_by(1.0)
+                                .text("Offset (ms)"),
+                        );
+                        let reset = ui.button("Reset").clicked();
+                        if reset {
+                            offset_ms = 0.0;
+                        }
+
+                        if response.changed() || reset {
+                            playback.offset_override = offset_ms / 1000.0;
+                            status.restart_song(playback.audio_offset + playback.offset_override);
+                        }
+                        // saved once the slider is let go of instead of on every step of a drag
+                        if response.drag_stopped()
+                            || (response.changed() && !response.dragged())
+                            || reset
+                        {
+                            let offset = (playback.offset_override != 0.0)
+                                .then_some(playback.offset_override);
+                            if let Err(e) = self
+                                .offset_overrides
+                                .set(&playback.beatmap_hash, offset)
+                            {
+                                self.errors.push(format!(
+                                    "Failed to save offsets to {}.\n{}",
+                                    self.offset_overrides.path().display(),
+                                    e
+                                ));
+                            }
+                        }
+                    });
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut status.playback_speed, 0.1..=2.0)
+                                .text("Playback speed")
+                                .step_by(0.05),
+                        )
+                        .changed()
+                    {
+                        status.clock.set_rate(status.clock_rate());
+                        status.restart_song(audio_offset);
+                    }
+                    ui.label(format!("Clock rate: {}x", status.clock.rate()));
+                    ui.checkbox(&mut status.pause_on_miss, "Pause on miss or slider break");
+
+                    // moving, clearing or changing the loop changes the speed of the pass
+                    let speed = status.speed();
+                    ui.horizontal(|ui| {
+                        let section_loop = &mut status.section_loop;
+                        if ui.button("Set A").clicked() {
+                            section_loop.set_start(status.play_time);
+                        }
+                        if ui.button("Set B").clicked() {
+                            section_loop.set_end(status.play_time);
+                        }
+                        for (name, point) in [("A", section_loop.start), ("B", section_loop.end)] {
+                            ui.label(format!(
+                                "{}: {}",
+                                name,
+                                point.map_or("-".to_string(), format_time)
+                            ));
+                        }
+                        ui.checkbox(&mut section_loop.enabled, "Loop A-B");
+                        ui.add(
+                            egui::DragValue::new(&mut section_loop.slowdown)
+                                .clamp_range(0.0..=0.5)
+                                .speed(0.01)
+                                .prefix("Slow down per pass: "),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut section_loop.count_in_beats)
+                                .clamp_range(0..=8)
+                                .prefix("Count-in beats: "),
+                        );
+                        ui.label(format!("Passes: {}", section_loop.passes));
+                        if ui.button("Clear loop").clicked() {
+                            section_loop.clear();
+                        }
+                    });
+                    if status.speed() != speed {
+                        status.clock.set_rate(status.clock_rate());
+                        status.restart_song(audio_offset);
+                    }
+                    ui.label(format!(
+                        "Audio offset: {:.0} ms ({:+.0} ms set by hand)",
+                        audio_offset * 1000.0,
+                        playback.offset_override * 1000.0
+                    ));
 
                     ui.label("Replay data:");
-                    ui.label(format!("Player: {}", playback.replay.player_name));
+                    ui.label(format!(
+                        "Player: {}",
+                        playback.replay.player_name.as_deref().unwrap_or_default()
+                    ));
+                    ui.label(format!("Mods: {}", playback.replay.mods));
                     ui.label(format!("Score: {}", playback.replay.score));
                     ui.label(format!("Max combo: {}", playback.replay.max_combo));
                     ui.label(format!("Misses: {}", playback.replay.count_miss));
+                    for (report, is_match) in [
+                        (
+                            playback.judgement_report.to_string(),
+                            playback.judgement_report.is_match(),
+                        ),
+                        (
+                            playback.score_report.to_string(),
+                            playback.score_report.is_match(),
+                        ),
+                    ] {
+                        if is_match {
+                            ui.label(report);
+                        } else {
+                            ui.colored_label(egui::Color32::RED, report);
+                        }
+                    }
 
                     // add slider with full screen width
 
-                    if status.playing {
-                        status.play_time += delta_time * status.playback_speed;
+                    // the song knows best where playback is, unless it can't be heard
+                    if status.volume > 0.0 && !status.audio_song_sink.empty() {
+                        status
+                            .clock
+                            .follow_audio(status.audio_song_sink.get_pos().as_secs_f64());
+                    }
+                    status.play_time = status.clock.time();
 
-                        if status.play_time + replay_offset
+                    if status.clock.is_playing() {
+                        if let Some((_, end)) = status.section_loop.region() {
+                            if status.play_time >= end {
+                                status.restart_loop(
+                                    &playback.replay,
+                                    &playback.beatmap,
+                                    audio_offset,
+                                );
+                            }
+                        }
+                        status.update_count_in(&playback.beatmap, audio_offset);
+                        // the song and its hit sounds wait for the count-in
+                        let counting_in = status.section_loop.count_in_end.is_some();
+
+                        if status.play_time
                             > playback.replay.replay_data[playback.replay.replay_data.len() - 1]
                                 .total_time as f64
                                 / 1000.0
@@ -318,8 +534,8 @@ impl Gui {
                             status.pause();
                         }
 
-                        while status.playing
-                            && status.play_time + replay_offset
+                        while status.clock.is_playing()
+                            && status.play_time
                                 > playback.replay.replay_data[status.replay_data_index].total_time
                                     as f64
                                     / 1000.0
@@ -327,7 +543,7 @@ impl Gui {
                             status.replay_data_index += 1;
                         }
 
-                        while status.playing {
+                        while status.clock.is_playing() {
                             if let Some(object) =
                                 playback.beatmap.hit_objects.get(status.hit_object_index)
                             {
@@ -339,53 +555,69 @@ impl Gui {
                                 break;
                             }
                         }
+
+                        let (judged_until, play_time) = (status.judged_until, status.play_time);
+                        let is_passed =
+                            |time: f64| !counting_in && time > judged_until && time <= play_time;
+                        for judgement in &playback.judgements {
+                            let is_slider_break = judgement.slider.as_ref().is_some_and(|slider| {
+                                slider.missed_ticks().any(|tick| is_passed(tick.time))
+                            });
+                            if is_slider_break && status.pause_on_miss {
+                                status.pause();
+                            }
+
+                            if is_passed(judgement.time)
+                                && judgement.result == HitResult::Miss
+                                && status.pause_on_miss
+                            {
+                                status.pause();
+                            }
+                        }
+                        for (time, hit_sound) in &playback.hit_sounds {
+                            if is_passed(*time) {
+                                status.play_hit_sound(hit_sound);
+                            }
+                        }
+                        status.judged_until = status.play_time;
                     }
 
                     let ApproachRate {
                         preempt, fade_in, ..
                     } = playback.beatmap.difficulty.approach_rate;
 
-                    ui.spacing_mut().slider_width = ui.available_width() - 100.0;
-                    if ui
-                        .add_sized(
-                            [ui.available_width(), 20.0],
-                            egui::Slider::new(
-                                &mut status.replay_data_index,
-                                0..=(playback.replay.replay_data.len() - 1),
-                            ),
-                        )
-                        .changed()
+                    match playback
+                        .timeline
+                        .show(ui, status.play_time, &status.section_loop)
                     {
-                        status.play_time = playback.replay.replay_data[status.replay_data_index]
-                            .total_time as f64
-                            / 1000.0;
-                        let source = status.audio_song_source.clone().skip_duration(
-                            std::time::Duration::from_secs_f64(status.play_time + audio_offset),
-                        );
-
-                        status.last_checked_cursor_index = 0;
-                        status.last_hit_object_index = None;
-
-                        status.next_hit_object_to_hit_index = 0;
-                        while let Some(object) = playback
-                            .beatmap
-                            .hit_objects
-                            .get(status.next_hit_object_to_hit_index)
-                        {
-                            if object.time as f64 / 1000.0 >= status.play_time - preempt {
-                                break;
-                            }
-                            status.next_hit_object_to_hit_index += 1;
+                        Some(TimelineSeek::Scrub(time)) => {
+                            status.scrub(time, &playback.replay, &playback.beatmap)
                         }
-                        status.hit_object_index = status.next_hit_object_to_hit_index;
-                        status.misses.clear();
-
-                        let audio_song_sink = &status.audio_song_sink;
-                        audio_song_sink.clear();
-                        audio_song_sink.append(source);
-                        if status.playing {
-                            audio_song_sink.play();
+                        Some(TimelineSeek::Seek(time)) => {
+                            status.seek(time, &playback.replay, &playback.beatmap, audio_offset)
                         }
+                        None => {}
+                    }
+
+                    let replay_duration = playback
+                        .replay
+                        .replay_data
+                        .last()
+                        .map_or(0.0, |frame| frame.total_time as f64 / 1000.0);
+                    render_hp_graph(
+                        ui,
+                        &playback.replay.life_bar,
+                        &playback.hp_simulation,
+                        status.play_time,
+                        replay_duration,
+                    );
+                    if let Some((time, hp)) = playback.hp_simulation.lowest() {
+                        ui.label(format!(
+                            "HP: {:.0}% Lowest: {:.0}% at {:.1}s (green: recorded, orange: simulated)",
+                            playback.hp_simulation.at(status.play_time) * 100.0,
+                            hp * 100.0,
+                            time
+                        ));
                     }
 
                     let offset = egui::Vec2::new(50.0, ui.cursor().min.y + 50.0);
@@ -412,81 +644,43 @@ impl Gui {
                         "Hit windows: 300: {} 100: {} 50: {}",
                         hit_window_300, hit_window_100, hit_window_50
                     ));
+                    let clock_rate = playback.replay.mods.clock_rate();
+                    ui.label(format!(
+                        "CS: {:.1} AR: {:.2} OD: {:.2} HP: {:.1} (with mods, at {}x)",
+                        playback.beatmap.difficulty.circle_size,
+                        playback
+                            .beatmap
+                            .difficulty
+                            .approach_rate
+                            .at_clock_rate(clock_rate)
+                            .value,
+                        playback
+                            .beatmap
+                            .difficulty
+                            .overall_difficulty
+                            .at_clock_rate(clock_rate)
+                            .value,
+                        playback.beatmap.difficulty.hit_point_drain_rate,
+                        clock_rate
+                    ));
 
-                    let next_hit_object_to_hit = playback
-                        .beatmap
-                        .hit_objects
-                        .get(status.next_hit_object_to_hit_index);
-                    let relative_hit_error = if let Some(object) = next_hit_object_to_hit {
-                        match object.hit_type {
-                            HitType::Spinner(Spinner {
-                                end_time
-                            }) => {
-                                status.play_time - end_time as f64 / 1000.0
-                            },
-                            _ => status.play_time - object.time as f64 / 1000.0
-                        }
-                    } else {
-                        0.0
-                    };
-
-                    if let Some(next_hit_object_to_hit) = next_hit_object_to_hit {
-                        match next_hit_object_to_hit.hit_type {
-                            HitType::Spinner(_) if relative_hit_error > 0.0 => {
-                                status.successful_hit(
-                                    status.next_hit_object_to_hit_index,
-                                    next_hit_object_to_hit,
-                                    time_offset
-                                );
-                            }
-                            _ => (),
-                        }
-                    }
-
-                    if relative_hit_error > hit_window_50 {
-                        match status.last_missed_hit_object {
-                            Some(last_index) => {
-                                if last_index < status.next_hit_object_to_hit_index {
-                                    match status.last_maybe_missed_hit_object {
-                                        Some(index) => {
-                                            if index != status.next_hit_object_to_hit_index {
-                                                status.miss_timeout(&playback.replay.replay_data[status.replay_data_index]);
-                                                status.next_hit_object_to_hit_index += 1;
-                                            } else {
-                                                status.last_maybe_missed_hit_object = None;
-                                                status.next_hit_object_to_hit_index += 1;
-                                            }
-                                        },
-                                        None => {
-                                            status.miss_timeout(&playback.replay.replay_data[status.replay_data_index]);
-                                            status.next_hit_object_to_hit_index += 1;
-                                        }
-                                    }
-                                }
-                            }
-                            None => {
-                                status.miss_timeout(&playback.replay.replay_data[status.replay_data_index]);
-                                status.next_hit_object_to_hit_index += 1;
-                            }
-                        }
-                    }
+                    let timing = playback.beatmap.timing_at(status.play_time * 1000.0);
+                    ui.label(format!(
+                        "BPM: {:.2} Slider velocity: {:.2}x Volume: {}%{}",
+                        timing.bpm(),
+                        timing.slider_velocity,
+                        timing.volume,
+                        if timing.kiai { " Kiai" } else { "" }
+                    ));
 
                     {
-                        let first = status.next_hit_object_to_hit_index;
-                        let last = {
-                            let mut last = first;
-                            while let Some(hit_object) = playback.beatmap.hit_objects.get(last) {
-                                if hit_object.time as f64 / 1000.0 > status.play_time + preempt {
-                                    break;
-                                }
-                                last += 1;
-                            }
-                            last
-                        };
+                        let last = playback.beatmap.hit_objects.partition_point(|hit_object| {
+                            hit_object.time as f64 / 1000.0 <= status.play_time + preempt
+                        });
 
                         ui.label(format!(
-                            "First: {} Last: {} Preempt: {} FadeIn: {}",
-                            first, last, preempt, fade_in
+                            "Last: {} Preempt: {} FadeIn: {}",
+                            last, preempt, fade_in
                         ));
                         ui.label(format!(
                             "Current: {} Time: {} Type {}",
@@ -497,129 +691,122 @@ impl Gui {
                                 .get(status.hit_object_index)
                                 .map(|o| o.time as f64 / 1000.0)
                                 .unwrap_or(-1.0),
-                            next_hit_object_to_hit
+                            playback
+                                .beatmap
+                                .hit_objects
+                                .get(status.hit_object_index)
                                 .map(|o| o.hit_type.to_string())
                                 .unwrap_or("Unknown".to_string())
                         ));
 
+                        let judged = || {
+                            playback
+                                .judgements
+                                .iter()
+                                .filter(|judgement| judgement.time <= status.play_time)
+                        };
+                        let count = |result: HitResult| {
+                            judged()
+                                .filter(|judgement| judgement.result == result)
+                                .count()
+                        };
                         ui.label(format!(
-                            "Current: {} Last Hit: {} Next Hit: {} Last Miss: {}",
-                            status.hit_object_index,
-                            status.last_hit_object_index.unwrap_or(0),
-                            status.next_hit_object_to_hit_index,
-                            status.last_missed_hit_object.unwrap_or(0)
+                            "300: {} 100: {} 50: {} Misses: {}",
+                            count(HitResult::Great),
+                            count(HitResult::Ok),
+                            count(HitResult::Meh),
+                            count(HitResult::Miss)
+                        ));
+                        let score_state = playback.score_simulation.at(status.play_time);
+                        ui.label(format!(
+                            "Combo: {}x (max {}x) Score: {}",
+                            score_state.combo, score_state.max_combo, score_state.score
+                        ));
+                        ui.label(format!(
+                            "Slider breaks: {}",
+                            judged()
+                                .filter(|judgement| {
+                                    judgement.result != HitResult::Miss
+                                        && judgement
+                                            .slider
+                                            .as_ref()
+                                            .is_some_and(|slider| slider.is_break())
+                                })
+                                .count()
                         ));
-                        ui.label(format!("Misses: {}", status.misses.len()));
+                        if let Some(judgement) =
+                            judged().max_by(|a, b| a.time.total_cmp(&b.time))
+                        {
+                            ui.label(format!(
+                                "Last judgement: {}{} on {} at frame {}{}",
+                                judgement.result,
+                                judgement
+                                    .miss_reason
+                                    .map(|reason| format!(" ({:?})", reason))
+                                    .unwrap_or_default(),
+                                judgement.hit_object_index,
+                                judgement.frame_index,
+                                judgement
+                                    .hit_error
+                                    .map(|error| format!(" ({:+.0}ms)", error * 1000.0))
+                                    .unwrap_or_default()
+                            ));
+                        }
 
-                        for i in (first..=last).rev() {
+                        // objects stay on screen until judged, sliders are judged at their end
+                        let is_visible = |index: usize| {
+                            playback
+                                .judgements
+                                .get(index)
+                                .map_or(true, |judgement| judgement.time > status.play_time)
+                        };
+
+                        for i in (0..last).rev() {
                             if let Some(hit_object) = playback.beatmap.hit_objects.get(i) {
+                                if !is_visible(i) {
+                                    continue;
+                                }
                                 hit_object.render(
                                     ui,
                                     &playback.beatmap,
+                                    &self.skin,
                                     status.play_time,
                                     scale,
                                     offset,
                                 );
-                                ui.painter().text(
-                                    egui::Pos2::new(
-                                        hit_object.x as f32 * scale + offset.x,
-                                        hit_object.y as f32 * scale + offset.y,
-                                    ),
-                                    egui::Align2::CENTER_CENTER,
-                                    format!("{}", i),
-                                    egui::FontId::default(),
-                                    egui::Color32::from_white_alpha(255),
-                                );
+
+                                let play_time_ms = status.play_time * 1000.0;
+                                if let HitType::Spinner(spinner) = &hit_object.hit_type {
+                                    if (hit_object.time as f64..=hit_object.end_time())
+                                        .contains(&play_time_ms)
+                                    {
+                                        let progress = SpinProgress::compute(
+                                            hit_object.time as f64,
+                                            spinner,
+                                            &playback.beatmap.difficulty,
+                                            // only the frames the cursor has been drawn up to
+                                            &playback.replay.replay_data
+                                                [..=status.replay_data_index],
+                                            play_time_ms,
+                                        );
+                                        render_spin_progress(&progress, ui, scale, offset);
+                                    }
+                                }
                             }
                         }
                     }
 
+                    if playback.beatmap.mods.contains(Mods::FLASHLIGHT) {
+                        let cursor = &playback.replay.replay_data[status.replay_data_index];
+                        let combo = playback.score_simulation.at(status.play_time).combo;
+                        render_flashlight(ui, (cursor.x, cursor.y), combo, scale, offset);
+                    }
+
                     {
                         fn is_key_down(keys: i32, key: Keys) -> bool {
                             keys & key as i32 != 0
                         }
 
-                        if let Some(cursor) = playback.replay.replay_data.get(status.replay_data_index) {
-                            let last_keys = if status.replay_data_index > 0 {
-                                playback
-                                    .replay
-                                    .replay_data
-                                    .get(status.replay_data_index - 1)
-                                    .map(|data| data.keys)
-                                    .unwrap_or(0)
-                            } else {
-                                0
-                            };
-
-                            let is_k1_pressed = is_key_down(cursor.keys, Keys::K1)
-                                && !is_key_down(last_keys, Keys::K1);
-                            let is_k2_pressed = is_key_down(cursor.keys, Keys::K2)
-                                && !is_key_down(last_keys, Keys::K2);
-
-                            if (is_k1_pressed || is_k2_pressed)
-                                && status.replay_data_index != status.last_checked_cursor_index
-                            {
-                                let mut hit_object_index = status.next_hit_object_to_hit_index;
-                                let mut maybe_misses = Vec::new();
-
-                                let mut break_index = 0;
-                                while let Some(object) =
-                                    playback.beatmap.hit_objects.get(hit_object_index)
-                                {
-                                    if break_index > 20 {
-                                        break;
-                                    } else {
-                                        break_index += 1;
-                                    }
-
-                                    let distance_to_object = ((cursor.x as f64 - object.x as f64)
-                                        .powi(2)
-                                        + (cursor.y as f64 - object.y as f64).powi(2))
-                                    .sqrt();
-                                    let size =
-                                        54.4 - 4.48 * playback.beatmap.difficulty.circle_size;
-                                    let time_diff = status.play_time - object.time as f64 / 1000.0;
-
-                                    match object.hit_type {
-                                        HitType::Circle | HitType::Slider(_) => {
-                                            if distance_to_object > size {
-                                                if time_diff.abs() < hit_window_50 {
-                                                    if status.pause_on_miss {
-                                                        status.pause();
-                                                    }
-                                                    maybe_misses.push(status.miss_aim(cursor));
-                                                    status.last_checked_cursor_index = status.replay_data_index;
-                                                } else {
-                                                    if time_diff > 0.0 {
-                                                        continue;
-                                                    } else {
-                                                        status.misses.append(&mut maybe_misses);
-                                                        break;
-                                                    }
-                                                }
-                                            } else {
-                                                // too early but not way to early
-                                                if time_diff < 0.0
-                                                    && time_diff.abs() > hit_window_50
-                                                    && time_diff.abs() < hit_window_50 * 2.0
-                                                {
-                                                    status.miss_timing(cursor);
-                                                    status.last_checked_cursor_index = status.replay_data_index;
-                                                    break;
-                                                } else {
-                                                    status.successful_hit(hit_object_index, object, time_offset);
-                                                    status.last_checked_cursor_index = status.replay_data_index;
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                        HitType::Spinner(_) => {}
-                                    }
-                                    hit_object_index += 1;
-                                }
-                            }
-                        }
-
                         let first = if status.replay_data_index > 20 {
                             (status.replay_data_index - 20) as usize
                         } else {
@@ -669,10 +856,25 @@ impl Gui {
                                 }
                             }
                         }
+
+                        let cursor = &playback.replay.replay_data[last];
+                        render_cursor(
+                            ui,
+                            &self.skin,
+                            (cursor.x, cursor.y),
+                            cursor.is_holding(),
+                            status.play_time,
+                            scale,
+                            offset,
+                        );
                     }
 
                     {
-                        for miss in &status.misses {
+                        let misses = playback
+                            .judgements
+                            .iter()
+                            .filter(|judgement| judgement.result == HitResult::Miss);
+                        for miss in misses {
                             let time_diff = status.play_time - miss.time;
                             if time_diff.abs() > 3.0 {
                                 continue;
@@ -681,10 +883,9 @@ impl Gui {
                             if let Some(missed_object) =
                                 playback.beatmap.hit_objects.get(miss.hit_object_index)
                             {
+                                let (x, y) = missed_object.position();
                                 ui.painter().circle_stroke(
-                                    egui::Pos2::new(missed_object.x as f32, missed_object.y as f32)
-                                        * scale
-                                        + offset,
+                                    egui::Pos2::new(x as f32, y as f32) * scale + offset,
                                     size as f32 * scale,
                                     egui::Stroke::new(
                                         1.0,
@@ -695,11 +896,7 @@ impl Gui {
                                 // draw line from circle to cursor
                                 ui.painter().line_segment(
                                     [
-                                        egui::Pos2::new(
-                                            missed_object.x as f32,
-                                            missed_object.y as f32,
-                                        ) * scale
-                                            + offset,
+                                        egui::Pos2::new(x as f32, y as f32) * scale + offset,
                                         egui::Pos2::new(
                                             miss.cursor_position.0 as f32,
                                             miss.cursor_position.1 as f32,
@@ -724,6 +921,62 @@ impl Gui {
                             );
                         }
                     }
+
+                    {
+                        let slider_break_color = egui::Color32::from_rgb(255, 140, 0);
+                        let size = playback.beatmap.difficulty.circle_radius();
+                        for judgement in &playback.judgements {
+                            let Some(slider) = &judgement.slider else {
+                                continue;
+                            };
+                            if judgement.result == HitResult::Miss {
+                                continue;
+                            }
+
+                            if !slider.head_hit && (status.play_time - judgement.time).abs() <= 3.0 {
+                                if let Some(head) =
+                                    playback.beatmap.hit_objects.get(judgement.hit_object_index)
+                                {
+                                    let (x, y) = head.position();
+                                    ui.painter().circle_stroke(
+                                        egui::Pos2::new(x as f32, y as f32) * scale + offset,
+                                        size as f32 * scale,
+                                        egui::Stroke::new(1.0, slider_break_color),
+                                    );
+                                }
+                            }
+
+                            for tick in slider.missed_ticks() {
+                                if (status.play_time - tick.time).abs() > 3.0 {
+                                    continue;
+                                }
+                                let tick_position = egui::Pos2::new(
+                                    tick.position.0 as f32,
+                                    tick.position.1 as f32,
+                                ) * scale
+                                    + offset;
+                                ui.painter().circle_stroke(
+                                    tick_position,
+                                    5.0,
+                                    egui::Stroke::new(2.0, slider_break_color),
+                                );
+
+                                // draw line from the tick to where the cursor was
+                                if let Some(cursor) = tick
+                                    .frame_index
+                                    .and_then(|index| playback.replay.replay_data.get(index))
+                                {
+                                    ui.painter().line_segment(
+                                        [
+                                            tick_position,
+                                            egui::Pos2::new(cursor.x, cursor.y) * scale + offset,
+                                        ],
+                                        egui::Stroke::new(1.0, slider_break_color),
+                                    );
+                                }
+                            }
+                        }
+                    }
                 }
             }
         });
@@ -743,72 +996,369 @@ impl Gui {
     }
 }
 
+/// Plots the life bar osu! recorded against the simulated HP over the whole replay.
+fn render_hp_graph(
+    ui: &mut egui::Ui,
+    life_bar: &[LifeBarPoint],
+    hp_simulation: &HpSimulation,
+    play_time: f64,
+    duration: f64,
+) {
+    let (rect, _) = ui.allocate_exact_size(
+        egui::Vec2::new(ui.available_width(), 60.0),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(255));
+    if duration <= 0.0 {
+        return;
+    }
+
+    let to_screen = |time: f64, hp: f64| {
+        egui::Pos2::new(
+            rect.left() + (time / duration) as f32 * rect.width(),
+            rect.bottom() - hp.clamp(0.0, 1.0) as f32 * rect.height(),
+        )
+    };
+
+    let recorded = life_bar
+        .iter()
+        .map(|point| to_screen(point.time as f64 / 1000.0, point.hp))
+        .collect();
+    painter.add(egui::Shape::line(
+        recorded,
+        egui::Stroke::new(1.5, egui::Color32::from_rgb(100, 220, 100)),
+    ));
+
+    let simulated = hp_simulation
+        .points
+        .iter()
+        .map(|(time, hp)| to_screen(*time, *hp))
+        .collect();
+    painter.add(egui::Shape::line(
+        simulated,
+        egui::Stroke::new(1.0, egui::Color32::from_rgb(255, 140, 0)),
+    ));
+
+    let x = to_screen(play_time, 0.0).x;
+    painter.line_segment(
+        [
+            egui::Pos2::new(x, rect.top()),
+            egui::Pos2::new(x, rect.bottom()),
+        ],
+        egui::Stroke::new(1.0, egui::Color32::from_white_alpha(255)),
+    );
+}
+
+fn load_replay(
+    osu_db_path: &Path,
+    beatmaps: &osu_db::listing::Listing,
+    replay_path: &Path,
+    skin_directory: Option<&Path>,
+    sample_directory: &Path,
+    offset_overrides: &OffsetOverrides,
+) -> Result<ReplayPlaybackData, String> {
+    let replay = crate::models::osu_replay::OsuReplay::from_file(replay_path).map_err(|e| {
+        format!(
+            "Failed to load replay from {}.\n{}",
+            replay_path.display(),
+            e
+        )
+    })?;
+
+    if replay.replay_data.is_empty() {
+        return Err(format!(
+            "Replay {} contains no frames.",
+            replay_path.display()
+        ));
+    }
+
+    let Some(beatmap_hash) = replay.beatmap_hash.clone() else {
+        return Err(format!(
+            "Replay {} has no beatmap hash.",
+            replay_path.display()
+        ));
+    };
+    let Some(beatmap_listing) = beatmaps
+        .beatmaps
+        .iter()
+        .find(|b| b.hash.as_ref() == Some(&beatmap_hash))
+    else {
+        return Err(format!(
+            "Failed to find beatmap with hash {}.",
+            beatmap_hash
+        ));
+    };
+
+    let (Some(osu_directory), Some(folder_name), Some(file_name)) = (
+        osu_db_path.parent(),
+        beatmap_listing.folder_name.as_ref(),
+        beatmap_listing.file_name.as_ref(),
+    ) else {
+        return Err(format!("Beatmap {} has no file in osu!.db.", beatmap_hash));
+    };
+    let osu_beatmap_path = osu_directory.join("Songs").join(folder_name);
+
+    let osu_file_path = osu_beatmap_path.join(file_name);
+
+    let mut beatmap = crate::models::osu_map::OsuMap::from_file(&osu_file_path).map_err(|e| {
+        format!(
+            "Failed to load beatmap from {}.\n{}",
+            osu_file_path.display(),
+            e
+        )
+    })?;
+
+    let (_stream, handle) = OutputStream::try_default()
+        .map_err(|e| format!("Failed to open the audio output.\n{}", e))?;
+
+    let audio_path = osu_beatmap_path.join(&beatmap.general.audio_filename);
+
+    let song_source = {
+        let file = File::open(&audio_path)
+            .map_err(|e| format!("Failed to open audio {}.\n{}", audio_path.display(), e))?;
+        Decoder::new(BufReader::new(file))
+            .map_err(|e| format!("Failed to decode audio {}.\n{}", audio_path.display(), e))?
+            .buffered()
+    };
+    let sink = Sink::try_new(&handle).map_err(|e| format!("Failed to play audio.\n{}", e))?;
+    sink.pause();
+
+    let sample_bank = SampleBank::load(
+        &beatmap,
+        &osu_beatmap_path,
+        skin_directory,
+        sample_directory,
+    );
+
+    let audio_offset = offset::audio_offset(&beatmap);
+    let offset_override = offset_overrides.get(&beatmap_hash).unwrap_or(0.0);
+    let start_time = offset::start_time(&beatmap, &replay);
+    let mut clock = PlaybackClock::new(MonotonicTime::new(), replay.mods.clock_rate());
+    clock.seek(start_time);
+
+    let status = PlaybackStatus {
+        playback_speed: 1.0,
+        mods: replay.mods,
+        clock,
+        play_time: start_time,
+        replay_data_index: 0,
+        audio_song_source: song_source,
+        sample_bank,
+        audio_stream_handle: handle,
+        audio_song_sink: sink,
+        hit_object_index: 0,
+        judged_until: start_time,
+        pause_on_miss: false,
+        go_to_time: String::new(),
+        section_loop: SectionLoop::default(),
+        volume: 1.0,
+    };
+
+    // ScoreV1 uses the difficulty the map has without mods
+    let difficulty_multiplier = score::difficulty_multiplier(&beatmap);
+    beatmap.apply_mods(replay.mods);
+
+    let judgements = judgement::judge(&beatmap, &replay);
+    let judgement_report = JudgementReport::new(&judgements, &replay);
+    let score_simulation =
+        ScoreSimulation::simulate(&beatmap, &replay, &judgements, difficulty_multiplier);
+    let score_report = ScoreReport::new(&score_simulation, &replay);
+    let hp_simulation = HpSimulation::simulate(&beatmap, &replay, &judgements);
+    let hit_sounds = hit_sound_events(&beatmap, &judgements);
+    let replay_end = replay
+        .replay_data
+        .last()
+        .map_or(0.0, |frame| frame.total_time as f64 / 1000.0);
+    let timeline = Timeline::new(&beatmap, &judgements, start_time, replay_end);
+
+    Ok(ReplayPlaybackData {
+        replay,
+        beatmap,
+        judgements,
+        judgement_report,
+        score_simulation,
+        score_report,
+        hp_simulation,
+        hit_sounds,
+        timeline,
+        replay_path: replay_path.display().to_string(),
+        beatmap_hash,
+        audio_output: _stream,
+        audio_offset,
+        offset_override,
+        playback_status: status,
+    })
+}
+
+/// Hit sounds of the heads, slider edges and spinners the player hit, at the time they were hit.
+fn hit_sound_events(beatmap: &OsuMap, judgements: &[Judgement]) -> Vec<(f64, HitSound)> {
+    let mut events = Vec::new();
+    for judgement in judgements {
+        let Some(object) = beatmap.hit_objects.get(judgement.hit_object_index) else {
+            continue;
+        };
+
+        match &judgement.slider {
+            Some(slider) => {
+                if slider.head_hit {
+                    let time = object.time as f64 / 1000.0 + judgement.hit_error.unwrap_or(0.0);
+                    events.push((time, beatmap.hit_sound(object, 0)));
+                }
+                let edges = slider
+                    .checkpoints
+                    .iter()
+                    .filter(|checkpoint| checkpoint.kind != CheckpointKind::Tick);
+                for (edge, checkpoint) in edges.enumerate() {
+                    if !checkpoint.hit {
+                        continue;
+                    }
+                    // the end is checked a bit early but sounds when the slider is over
+                    let time = match checkpoint.kind {
+                        CheckpointKind::End => object.end_time() / 1000.0,
+                        _ => checkpoint.time,
+                    };
+                    events.push((time, beatmap.hit_sound(object, edge + 1)));
+                }
+            }
+            None if judgement.result != HitResult::Miss => {
+                events.push((judgement.time, beatmap.hit_sound(object, 0)))
+            }
+            None => {}
+        }
+    }
+    events.sort_by(|a, b| a.0.total_cmp(&b.0));
+    events
+}
+
 impl PlaybackStatus {
-    fn successful_hit(&mut self, index: usize, _hit_object: &HitObject, offset: f64) {
-        self.audio_stream_handle
-            .play_raw(self.hit_sound_source.clone().amplify(self.volume as f32).convert_samples())
-            .unwrap();
+    fn play_hit_sound(&self, hit_sound: &HitSound) {
+        self.sample_bank
+            .play(&self.audio_stream_handle, hit_sound, self.volume);
+    }
 
-        let time_diff = self.play_time - _hit_object.time as f64 / 1000.0;
-        println!("timing: {}", time_diff);
+    /// The playback speed with the section loop's slowdown.
+    fn speed(&self) -> f64 {
+        self.section_loop.speed(self.playback_speed)
+    }
 
-        self.next_hit_object_to_hit_index = index + 1;
-        self.last_hit_object_index = Some(index);
+    /// How many seconds of map time pass per real second.
+    fn clock_rate(&self) -> f64 {
+        self.mods.clock_rate() * self.speed()
     }
 
     fn play(&mut self, audio_offset: f64) {
-        let source =
-            self.audio_song_source
-                .clone()
-                .skip_duration(std::time::Duration::from_secs_f64(
-                    self.play_time + audio_offset,
-                ));
-        self.playing = true;
-
-        self.audio_song_sink.set_speed(self.playback_speed as f32);
-        self.audio_song_sink.append(source);
-        self.audio_song_sink.play();
+        self.clock.play();
+        self.restart_song(audio_offset);
     }
 
-    fn pause(&mut self) {
-        self.playing = false;
-        self.audio_song_sink.pause();
+    /// Queues the song from the current play time at the current clock rate.
+    ///
+    /// DT and HT keep the song's pitch like osu! does, while NC speeds it up by resampling.
+    /// The playback speed is always pitch-preserved on top of that.
+    fn restart_song(&mut self, audio_offset: f64) {
+        let (stretch, resample) = if self.mods.contains(Mods::NIGHTCORE) {
+            (self.speed(), self.mods.clock_rate())
+        } else {
+            (self.clock_rate(), 1.0)
+        };
+        let play_time = self.clock.time();
+        let song_time = play_time + audio_offset;
+        let source = self
+            .audio_song_source
+            .clone()
+            .skip_duration(Duration::from_secs_f64(song_time.max(0.0)))
+            .convert_samples::<f32>();
+        // before the song starts, wait for it in silence so its position stays in step
+        // with the play time
+        let delay = (-song_time).max(0.0) / self.clock_rate() * resample;
+
         self.audio_song_sink.clear();
+        self.audio_song_sink.set_speed(resample as f32);
+        self.audio_song_sink
+            .append(TimeStretch::new(source, stretch).delay(Duration::from_secs_f64(delay)));
+        if self.clock.is_playing() {
+            self.audio_song_sink.play();
+            self.clock.start_audio();
+        }
     }
 
-    fn miss(&mut self, cursor: &crate::models::osu_replay::ReplayData) {
-        if self.pause_on_miss {
-            self.pause();
-        }
-        let time_diff = self.play_time - cursor.total_time as f64 / 1000.0;
-        println!("timing: {}", time_diff);
+    /// Jumps to `time`, with the replay and objects where they are at that time.
+    /// Everything judged before it counts as played, so it doesn't sound or pause on a miss.
+    fn seek(&mut self, time: f64, replay: &OsuReplay, beatmap: &OsuMap, audio_offset: f64) {
+        self.move_to(time, replay, beatmap);
+        self.restart_song(audio_offset);
+    }
 
-        self.misses.push(Miss {
-            time: self.play_time,
-            hit_object_index: self.next_hit_object_to_hit_index,
-            cursor_position: (cursor.x as f64, cursor.y as f64),
-        });
+    /// Like [`PlaybackStatus::seek`] but with the song silent until the next seek, for
+    /// dragging through time without decoding the song up to every step.
+    fn scrub(&mut self, time: f64, replay: &OsuReplay, beatmap: &OsuMap) {
+        self.move_to(time, replay, beatmap);
+        self.audio_song_sink.clear();
+    }
+
+    fn move_to(&mut self, time: f64, replay: &OsuReplay, beatmap: &OsuMap) {
+        let frames = &replay.replay_data;
+        self.clock.seek(time);
+        self.play_time = time;
+        self.replay_data_index = frames
+            .partition_point(|frame| (frame.total_time as f64 / 1000.0) < time)
+            .min(frames.len() - 1);
+        self.hit_object_index = beatmap
+            .hit_objects
+            .partition_point(|object| (object.time as f64 / 1000.0) <= time);
+        self.judged_until = time;
+        self.section_loop.count_in_end = None;
     }
 
-    fn miss_timing(&mut self, cursor: &crate::models::osu_replay::ReplayData) {
-        println!("missed on timing");
-        self.last_missed_hit_object = Some(self.next_hit_object_to_hit_index);
-        self.miss(cursor);
+    /// Goes back to A for the next pass of the section loop, slower if it slows down,
+    /// and starts the count-in if it has one.
+    fn restart_loop(&mut self, replay: &OsuReplay, beatmap: &OsuMap, audio_offset: f64) {
+        let Some((start, _)) = self.section_loop.region() else {
+            return;
+        };
+        self.section_loop.passes += 1;
+        self.clock.set_rate(self.clock_rate());
+
+        let ticks = self.section_loop.count_in_ticks(beatmap);
+        match ticks.first() {
+            Some(&count_in_start) => {
+                self.seek(count_in_start, replay, beatmap, audio_offset);
+                self.audio_song_sink.clear();
+                self.section_loop.count_in_end = Some(start);
+                // the first tick is right where the count-in starts
+                self.play_hit_sound(&HitSound::click(
+                    beatmap.timing_at(start * 1000.0).sample_set,
+                ));
+            }
+            None => self.seek(start, replay, beatmap, audio_offset),
+        }
     }
 
-    fn miss_aim(&mut self, cursor: &crate::models::osu_replay::ReplayData) -> Miss {
-        println!("missed on aim");
-        self.last_maybe_missed_hit_object = Some(self.next_hit_object_to_hit_index);
-        Miss {
-            time: self.play_time,
-            hit_object_index: self.next_hit_object_to_hit_index,
-            cursor_position: (cursor.x as f64, cursor.y as f64),
+    /// Ticks the count-in beats passed since the last frame, and starts the song at A
+    /// once they are over.
+    fn update_count_in(&mut self, beatmap: &OsuMap, audio_offset: f64) {
+        let Some(count_in_end) = self.section_loop.count_in_end else {
+            return;
+        };
+        if self.play_time >= count_in_end {
+            self.section_loop.count_in_end = None;
+            self.restart_song(audio_offset);
+            return;
+        }
+
+        let click = HitSound::click(beatmap.timing_at(count_in_end * 1000.0).sample_set);
+        for tick in self.section_loop.count_in_ticks(beatmap) {
+            if tick > self.judged_until && tick <= self.play_time {
+                self.play_hit_sound(&click);
+            }
         }
     }
 
-    fn miss_timeout(&mut self, cursor: &crate::models::osu_replay::ReplayData) {
-        println!("missed on timeout");
-        self.last_missed_hit_object = Some(self.next_hit_object_to_hit_index);
-        self.miss(cursor);
+    fn pause(&mut self) {
+        self.clock.pause();
+        self.section_loop.count_in_end = None;
+        self.audio_song_sink.pause();
+        self.audio_song_sink.clear();
     }
 }
@@ -0,0 +1,148 @@
+use crate::models::osu_map::OsuMap;
+
+/// Slowest playback speed a loop slows down to.
+pub const MIN_LOOP_SPEED: f64 = 0.1;
+
+/// A section between an A and a B point that playback repeats, for practicing it.
+#[derive(Debug, Clone)]
+pub struct SectionLoop {
+    /// Play time of A in seconds.
+    pub(crate) start: Option<f64>,
+    /// Play time of B in seconds.
+    pub(crate) end: Option<f64>,
+    pub(crate) enabled: bool,
+    /// Playback speed taken off after every pass, 0 to keep the speed.
+    pub(crate) slowdown: f64,
+    /// Beats ticked before each pass with the song silent, 0 for none.
+    pub(crate) count_in_beats: u32,
+    /// Passes played since the loop was set.
+    pub(crate) passes: u32,
+    /// Play time the count-in ends and the song starts at, while counting in.
+    pub(crate) count_in_end: Option<f64>,
+}
+
+impl Default for SectionLoop {
+    fn default() -> Self {
+        SectionLoop {
+            start: None,
+            end: None,
+            enabled: true,
+            slowdown: 0.0,
+            count_in_beats: 0,
+            passes: 0,
+            count_in_end: None,
+        }
+    }
+}
+
+impl SectionLoop {
+    /// A and B when both are set the right way round and the loop is on.
+    pub fn region(&self) -> Option<(f64, f64)> {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if self.enabled && start < end => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    /// Playback speed of the current pass, `base_speed` slowed down once for every pass
+    /// so far.
+    pub fn speed(&self, base_speed: f64) -> f64 {
+        if self.region().is_none() || self.slowdown <= 0.0 {
+            return base_speed;
+        }
+        (base_speed - self.passes as f64 * self.slowdown).max(MIN_LOOP_SPEED.min(base_speed))
+    }
+
+    pub fn set_start(&mut self, time: f64) {
+        self.start = Some(time);
+        self.passes = 0;
+    }
+
+    pub fn set_end(&mut self, time: f64) {
+        self.end = Some(time);
+        self.passes = 0;
+    }
+
+    pub fn clear(&mut self) {
+        *self = SectionLoop {
+            slowdown: self.slowdown,
+            count_in_beats: self.count_in_beats,
+            ..SectionLoop::default()
+        };
+    }
+
+    /// Play times of the count-in ticks before A, in map time at the tempo of A.
+    pub fn count_in_ticks(&self, beatmap: &OsuMap) -> Vec<f64> {
+        let Some(start) = self.start else {
+            return Vec::new();
+        };
+        let beat_length = beatmap.timing_at(start * 1000.0).beat_length / 1000.0;
+        (1..=self.count_in_beats)
+            .rev()
+            .map(|beat| start - beat as f64 * beat_length)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section_loop(start: f64, end: f64) -> SectionLoop {
+        let mut section_loop = SectionLoop::default();
+        section_loop.set_start(start);
+        section_loop.set_end(end);
+        section_loop
+    }
+
+    #[test]
+    fn loops_between_a_and_b() {
+        assert_eq!(SectionLoop::default().region(), None);
+        assert_eq!(section_loop(2.0, 5.0).region(), Some((2.0, 5.0)));
+        assert_eq!(section_loop(5.0, 2.0).region(), None);
+        assert_eq!(section_loop(2.0, 2.0).region(), None);
+
+        let mut section_loop = section_loop(2.0, 5.0);
+        section_loop.enabled = false;
+        assert_eq!(section_loop.region(), None);
+    }
+
+    #[test]
+    fn counts_in_at_the_tempo_of_a() {
+        let beatmap: OsuMap = "[TimingPoints]\n0,500,4,2,0,60,1,0\n10000,250,4,2,0,60,1,0\n"
+            .parse()
+            .unwrap();
+        let mut section_loop = section_loop(12.0, 15.0);
+        assert!(section_loop.count_in_ticks(&beatmap).is_empty());
+
+        section_loop.count_in_beats = 4;
+        assert_eq!(
+            section_loop.count_in_ticks(&beatmap),
+            [11.0, 11.25, 11.5, 11.75]
+        );
+        section_loop.set_start(5.0);
+        assert_eq!(section_loop.count_in_ticks(&beatmap), [3.0, 3.5, 4.0, 4.5]);
+    }
+
+    #[test]
+    fn slows_down_from_the_base_speed_every_pass() {
+        let mut section_loop = section_loop(2.0, 5.0);
+        section_loop.slowdown = 0.25;
+        assert_eq!(section_loop.speed(1.5), 1.5);
+
+        section_loop.passes = 2;
+        assert_eq!(section_loop.speed(1.5), 1.0);
+        // the same passes give the same speed however often it is asked for
+        assert_eq!(section_loop.speed(1.5), 1.0);
+
+        section_loop.passes = 10;
+        assert_eq!(section_loop.speed(1.5), MIN_LOOP_SPEED);
+        assert_eq!(section_loop.speed(0.05), 0.05);
+
+        section_loop.enabled = false;
+        assert_eq!(section_loop.speed(1.5), 1.5);
+        section_loop.enabled = true;
+        section_loop.set_start(3.0);
+        assert_eq!(section_loop.speed(1.5), 1.5);
+    }
+}
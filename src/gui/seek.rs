@@ -0,0 +1,150 @@
+use egui::{Key, Modifiers};
+
+use crate::{
+    judgement::{HitResult, Judgement},
+    models::{osu_map::OsuMap, osu_replay::OsuReplay},
+};
+
+/// Seconds the arrow keys seek by.
+const SEEK_STEP: f64 = 5.0;
+
+/// Keys that seek, most specific first as egui ignores extra modifiers.
+pub const SHORTCUTS: [(Modifiers, Key, SeekTarget); 8] = [
+    (Modifiers::COMMAND, Key::ArrowLeft, SeekTarget::PreviousMiss),
+    (Modifiers::COMMAND, Key::ArrowRight, SeekTarget::NextMiss),
+    (Modifiers::SHIFT, Key::ArrowLeft, SeekTarget::PreviousObject),
+    (Modifiers::SHIFT, Key::ArrowRight, SeekTarget::NextObject),
+    (
+        Modifiers::NONE,
+        Key::ArrowLeft,
+        SeekTarget::Relative(-SEEK_STEP),
+    ),
+    (
+        Modifiers::NONE,
+        Key::ArrowRight,
+        SeekTarget::Relative(SEEK_STEP),
+    ),
+    (Modifiers::NONE, Key::Comma, SeekTarget::PreviousFrame),
+    (Modifiers::NONE, Key::Period, SeekTarget::NextFrame),
+];
+
+/// Somewhere to jump to in a replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekTarget {
+    /// Play time in seconds.
+    Time(f64),
+    /// Seconds from the current play time.
+    Relative(f64),
+    NextFrame,
+    PreviousFrame,
+    NextObject,
+    PreviousObject,
+    NextMiss,
+    PreviousMiss,
+}
+
+impl SeekTarget {
+    /// Play time of the target from `play_time`, or `None` if there is nothing to jump to.
+    pub fn resolve(
+        self,
+        play_time: f64,
+        replay: &OsuReplay,
+        beatmap: &OsuMap,
+        judgements: &[Judgement],
+    ) -> Option<f64> {
+        let mut frame_times = replay
+            .replay_data
+            .iter()
+            .map(|frame| frame.total_time as f64 / 1000.0);
+        let mut object_times = beatmap
+            .hit_objects
+            .iter()
+            .map(|object| object.time as f64 / 1000.0);
+        let miss_times = judgements
+            .iter()
+            .filter(|judgement| judgement.result == HitResult::Miss)
+            .map(|judgement| judgement.time);
+
+        match self {
+            SeekTarget::Time(time) => Some(time),
+            SeekTarget::Relative(seconds) => Some(play_time + seconds),
+            SeekTarget::NextFrame => frame_times.find(|time| *time > play_time),
+            SeekTarget::PreviousFrame => frame_times.rev().find(|time| *time < play_time),
+            SeekTarget::NextObject => object_times.find(|time| *time > play_time),
+            SeekTarget::PreviousObject => object_times.rev().find(|time| *time < play_time),
+            SeekTarget::NextMiss => miss_times
+                .filter(|time| *time > play_time)
+                .min_by(f64::total_cmp),
+            SeekTarget::PreviousMiss => miss_times
+                .filter(|time| *time < play_time)
+                .max_by(f64::total_cmp),
+        }
+    }
+
+    /// Stepping frame by frame is only useful paused.
+    pub fn pauses(self) -> bool {
+        matches!(self, SeekTarget::NextFrame | SeekTarget::PreviousFrame)
+    }
+}
+
+/// Parses a time typed by the user, in milliseconds or as `m:ss.mmm`.
+///
+/// Negative times and seconds past a minute are typos rather than somewhere to seek to.
+pub fn parse_time(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let time = match text.split_once(':') {
+        Some((minutes, seconds)) => {
+            let minutes: u64 = minutes.trim().parse().ok()?;
+            let seconds: f64 = seconds.trim().parse().ok()?;
+            if !(0.0..60.0).contains(&seconds) {
+                return None;
+            }
+            minutes as f64 * 60.0 + seconds
+        }
+        None => text.parse::<f64>().ok()? / 1000.0,
+    };
+    (time.is_finite() && time >= 0.0).then_some(time)
+}
+
+/// Formats a play time as `m:ss.mmm`, which [`parse_time`] reads back from 0 on.
+pub fn format_time(time: f64) -> String {
+    let milliseconds = (time.abs() * 1000.0).round() as u64;
+    format!(
+        "{}{}:{:02}.{:03}",
+        if time < 0.0 { "-" } else { "" },
+        milliseconds / 60_000,
+        milliseconds / 1000 % 60,
+        milliseconds % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_formatted_times_back() {
+        for time in [0.0, 0.5, 59.999, 61.25, 754.321] {
+            let text = format_time(time);
+            assert_eq!(parse_time(&text), Some(time), "{}", text);
+        }
+        assert_eq!(format_time(61.25), "1:01.250");
+        assert_eq!(format_time(-1.5), "-0:01.500");
+    }
+
+    #[test]
+    fn reads_milliseconds() {
+        assert_eq!(parse_time("1500"), Some(1.5));
+        assert_eq!(parse_time(" 250.5 "), Some(0.2505));
+        assert_eq!(parse_time("2 : 03.5"), Some(123.5));
+    }
+
+    #[test]
+    fn rejects_malformed_times() {
+        for text in [
+            "1:75", "-3", "", "1:2:3", "-1:00", "1.5:00", "abc", "inf", "NaN",
+        ] {
+            assert_eq!(parse_time(text), None, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{
+    gui::{section_loop::SectionLoop, seek::format_time},
+    judgement::{HitResult, Judgement},
+    models::osu_map::OsuMap,
+};
+
+const TIMELINE_HEIGHT: f32 = 48.0;
+/// Markers this close to the pointer in screen pixels are clicked instead of the time under it.
+const MARKER_CLICK_DISTANCE: f32 = 4.0;
+/// Rough width of a bar of the density graph in screen pixels.
+const DENSITY_BAR_WIDTH: f32 = 3.0;
+
+/// Something worth jumping to on the timeline, in the order they are drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MarkerKind {
+    Meh,
+    Ok,
+    SliderBreak,
+    Miss,
+}
+
+impl MarkerKind {
+    fn name(self) -> &'static str {
+        match self {
+            MarkerKind::Meh => "50",
+            MarkerKind::Ok => "100",
+            MarkerKind::SliderBreak => "Slider break",
+            MarkerKind::Miss => "Miss",
+        }
+    }
+
+    fn colour(self) -> egui::Color32 {
+        match self {
+            MarkerKind::Meh => egui::Color32::from_rgb(218, 174, 70),
+            MarkerKind::Ok => egui::Color32::from_rgb(87, 227, 19),
+            MarkerKind::SliderBreak => egui::Color32::from_rgb(255, 120, 200),
+            MarkerKind::Miss => egui::Color32::from_rgb(255, 40, 40),
+        }
+    }
+
+    /// Share of the timeline's height the marker reaches up to.
+    fn height(self) -> f32 {
+        match self {
+            MarkerKind::Meh | MarkerKind::Ok => 0.35,
+            MarkerKind::SliderBreak => 0.7,
+            MarkerKind::Miss => 1.0,
+        }
+    }
+}
+
+/// Where the timeline asks playback to go.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimelineSeek {
+    /// Play time under the pointer while dragging, the song waits for the drag to stop.
+    Scrub(f64),
+    /// Play time clicked or where a drag stopped.
+    Seek(f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Marker {
+    /// Play time in seconds.
+    pub(crate) time: f64,
+    pub(crate) kind: MarkerKind,
+}
+
+/// The full length of a replay at a glance: judgement markers, hit errors, breaks, kiai and
+/// how dense the objects are, with the play time on top. Clicking it seeks.
+pub struct Timeline {
+    /// Play times the timeline starts and ends at, in seconds.
+    start: f64,
+    end: f64,
+    /// Sorted by kind so misses are drawn over the rest.
+    markers: Vec<Marker>,
+    /// `(object time, hit error)` of every hit in seconds, with the colour of its result.
+    hit_errors: Vec<(f64, f64, egui::Color32)>,
+    /// Hit error at the edge of the timeline's height, the 50 window.
+    max_hit_error: f64,
+    /// `(start, end)` in seconds.
+    breaks: Vec<(f64, f64)>,
+    kiai_sections: Vec<(f64, f64)>,
+    object_times: Vec<f64>,
+}
+
+impl Timeline {
+    pub fn new(beatmap: &OsuMap, judgements: &[Judgement], start: f64, end: f64) -> Self {
+        let mut markers = Vec::new();
+        let mut hit_errors = Vec::new();
+        for judgement in judgements {
+            if let (Some(hit_error), Some(object)) = (
+                judgement.hit_error,
+                beatmap.hit_objects.get(judgement.hit_object_index),
+            ) {
+                let colour = match judgement.result {
+                    HitResult::Great => egui::Color32::from_rgb(50, 188, 231),
+                    HitResult::Ok => MarkerKind::Ok.colour(),
+                    HitResult::Meh => MarkerKind::Meh.colour(),
+                    HitResult::Miss => MarkerKind::Miss.colour(),
+                };
+                hit_errors.push((object.time as f64 / 1000.0, hit_error, colour));
+            }
+
+            let kind = match judgement.result {
+                HitResult::Great => None,
+                HitResult::Ok => Some(MarkerKind::Ok),
+                HitResult::Meh => Some(MarkerKind::Meh),
+                HitResult::Miss => Some(MarkerKind::Miss),
+            };
+            if let Some(kind) = kind {
+                markers.push(Marker {
+                    time: judgement.time,
+                    kind,
+                });
+            }
+            if let Some(slider) = &judgement.slider {
+                markers.extend(slider.missed_ticks().map(|tick| Marker {
+                    time: tick.time,
+                    kind: MarkerKind::SliderBreak,
+                }));
+            }
+        }
+        markers.sort_by_key(|marker| marker.kind);
+
+        let to_seconds = |(start, end): (f64, f64)| (start / 1000.0, end / 1000.0);
+        Timeline {
+            start,
+            end,
+            markers,
+            hit_errors,
+            max_hit_error: beatmap.difficulty.overall_difficulty.hit_window_50,
+            breaks: beatmap
+                .events
+                .breaks
+                .iter()
+                .map(|b| to_seconds((b.start_time as f64, b.end_time as f64)))
+                .collect(),
+            kiai_sections: beatmap
+                .kiai_sections()
+                .into_iter()
+                .map(to_seconds)
+                .collect(),
+            object_times: beatmap
+                .hit_objects
+                .iter()
+                .map(|object| object.time as f64 / 1000.0)
+                .collect(),
+        }
+    }
+
+    /// Screen x of the play time `time` on the timeline drawn in `rect`.
+    fn x_at(&self, time: f64, rect: egui::Rect) -> f32 {
+        rect.left() + ((time - self.start) / (self.end - self.start)) as f32 * rect.width()
+    }
+
+    /// Play time at the screen x `x` on the timeline drawn in `rect`.
+    fn time_at(&self, x: f32, rect: egui::Rect) -> f64 {
+        self.start + ((x - rect.left()) / rect.width()) as f64 * (self.end - self.start)
+    }
+
+    /// Objects in each of `bars` equally long parts of the timeline.
+    fn density(&self, bars: usize) -> Vec<usize> {
+        let mut density = vec![0; bars];
+        for time in &self.object_times {
+            let position = (time - self.start) / (self.end - self.start);
+            if (0.0..1.0).contains(&position) {
+                density[(position * bars as f64) as usize] += 1;
+            }
+        }
+        density
+    }
+
+    /// Draws the timeline over the full width and returns where to go if it was clicked
+    /// or dragged.
+    pub fn show(
+        &self,
+        ui: &mut egui::Ui,
+        play_time: f64,
+        section_loop: &SectionLoop,
+    ) -> Option<TimelineSeek> {
+        let (rect, response) = ui.allocate_exact_size(
+            egui::Vec2::new(ui.available_width(), TIMELINE_HEIGHT),
+            egui::Sense::click_and_drag(),
+        );
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(255));
+        if self.end <= self.start {
+            return None;
+        }
+
+        let to_x = |time: f64| self.x_at(time, rect);
+        let to_time = |x: f32| self.time_at(x, rect);
+        let span = |(start, end): (f64, f64), top: f32, bottom: f32| {
+            egui::Rect::from_x_y_ranges(to_x(start)..=to_x(end), top..=bottom)
+        };
+
+        for section in &self.breaks {
+            painter.rect_filled(
+                span(*section, rect.top(), rect.bottom()),
+                0.0,
+                egui::Color32::from_gray(40),
+            );
+        }
+        for section in &self.kiai_sections {
+            painter.rect_filled(
+                span(*section, rect.top(), rect.top() + 4.0),
+                0.0,
+                egui::Color32::from_rgb(255, 150, 0),
+            );
+        }
+
+        // objects per bar, highest bar at half the height
+        let bars = (rect.width() / DENSITY_BAR_WIDTH).max(1.0) as usize;
+        let density = self.density(bars);
+        let max_density = density.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let bar_width = rect.width() / bars as f32;
+        for (bar, count) in density.into_iter().enumerate() {
+            let left = rect.left() + bar as f32 * bar_width;
+            let height = count as f32 / max_density * rect.height() * 0.5;
+            painter.rect_filled(
+                egui::Rect::from_x_y_ranges(
+                    left..=left + bar_width,
+                    rect.bottom() - height..=rect.bottom(),
+                ),
+                0.0,
+                egui::Color32::from_rgb(60, 80, 120),
+            );
+        }
+
+        if let Some(region) = section_loop.region() {
+            painter.rect_filled(
+                span(region, rect.top(), rect.bottom()),
+                0.0,
+                egui::Color32::from_rgba_unmultiplied(80, 140, 255, 50),
+            );
+        }
+        for (name, point) in [("A", section_loop.start), ("B", section_loop.end)] {
+            if let Some(time) = point {
+                let x = to_x(time);
+                painter.line_segment(
+                    [
+                        egui::Pos2::new(x, rect.top()),
+                        egui::Pos2::new(x, rect.bottom()),
+                    ],
+                    egui::Stroke::new(1.0, egui::Color32::from_rgb(80, 140, 255)),
+                );
+                painter.text(
+                    egui::Pos2::new(x + 2.0, rect.top() + 4.0),
+                    egui::Align2::LEFT_TOP,
+                    name,
+                    egui::FontId::proportional(10.0),
+                    egui::Color32::from_rgb(80, 140, 255),
+                );
+            }
+        }
+
+        // hit errors around the middle, early above and late below
+        let middle = rect.center().y;
+        painter.line_segment(
+            [
+                egui::Pos2::new(rect.left(), middle),
+                egui::Pos2::new(rect.right(), middle),
+            ],
+            egui::Stroke::new(1.0, egui::Color32::from_gray(60)),
+        );
+        for (time, hit_error, colour) in &self.hit_errors {
+            let offset = (hit_error / self.max_hit_error).clamp(-1.0, 1.0) as f32;
+            painter.circle_filled(
+                egui::Pos2::new(to_x(*time), middle + offset * rect.height() * 0.45),
+                1.0,
+                *colour,
+            );
+        }
+
+        for marker in &self.markers {
+            let x = to_x(marker.time);
+            let top = rect.bottom() - marker.kind.height() * rect.height();
+            painter.line_segment(
+                [egui::Pos2::new(x, top), egui::Pos2::new(x, rect.bottom())],
+                egui::Stroke::new(1.0, marker.kind.colour()),
+            );
+        }
+
+        let x = to_x(play_time);
+        painter.line_segment(
+            [
+                egui::Pos2::new(x, rect.top()),
+                egui::Pos2::new(x, rect.bottom()),
+            ],
+            egui::Stroke::new(2.0, egui::Color32::from_white_alpha(255)),
+        );
+
+        // the last scrubbed time, the pointer may be off the timeline by now
+        if response.drag_stopped() {
+            return Some(TimelineSeek::Seek(play_time));
+        }
+
+        let pointer = response.interact_pointer_pos().or(response.hover_pos())?;
+        // the most important marker near the pointer, closest first
+        let marker = self
+            .markers
+            .iter()
+            .filter(|marker| (to_x(marker.time) - pointer.x).abs() <= MARKER_CLICK_DISTANCE)
+            .max_by(|a, b| {
+                a.kind.cmp(&b.kind).then_with(|| {
+                    let distance = |marker: &Marker| (to_x(marker.time) - pointer.x).abs();
+                    distance(b).total_cmp(&distance(a))
+                })
+            });
+
+        let time = marker.map_or(to_time(pointer.x), |marker| marker.time);
+        let text = match marker {
+            Some(marker) => format!("{} at {}", marker.kind.name(), format_time(marker.time)),
+            None => format_time(time),
+        };
+        let response = response.on_hover_text_at_pointer(text);
+
+        // dragging scrubs through time without snapping to markers
+        if response.dragged() {
+            Some(TimelineSeek::Scrub(
+                to_time(pointer.x).clamp(self.start, self.end),
+            ))
+        } else if response.clicked() {
+            Some(TimelineSeek::Seek(time.clamp(self.start, self.end)))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeline(start: f64, end: f64, object_times: &[f64]) -> Timeline {
+        let beatmap: OsuMap = "".parse().unwrap();
+        Timeline {
+            object_times: object_times.to_vec(),
+            ..Timeline::new(&beatmap, &[], start, end)
+        }
+    }
+
+    fn rect() -> egui::Rect {
+        egui::Rect::from_min_size(egui::Pos2::new(100.0, 0.0), egui::Vec2::new(500.0, 48.0))
+    }
+
+    #[test]
+    fn maps_times_across_the_width() {
+        let timeline = timeline(-2.0, 8.0, &[]);
+        assert_eq!(timeline.x_at(-2.0, rect()), 100.0);
+        assert_eq!(timeline.x_at(3.0, rect()), 350.0);
+        assert_eq!(timeline.x_at(8.0, rect()), 600.0);
+        // off the ends, for markers the drawing clips
+        assert_eq!(timeline.x_at(10.0, rect()), 700.0);
+    }
+
+    #[test]
+    fn maps_positions_back_to_times() {
+        let timeline = timeline(-2.0, 8.0, &[]);
+        assert_eq!(timeline.time_at(100.0, rect()), -2.0);
+        assert_eq!(timeline.time_at(350.0, rect()), 3.0);
+        assert_eq!(timeline.time_at(600.0, rect()), 8.0);
+        for time in [-1.5, 0.25, 7.9] {
+            let x = timeline.x_at(time, rect());
+            assert!((timeline.time_at(x, rect()) - time).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn buckets_objects_by_time() {
+        let timeline = timeline(0.0, 10.0, &[-0.5, 1.0, 1.5, 2.0, 9.999, 10.0]);
+        // objects before the start and at the end are off the timeline
+        assert_eq!(timeline.density(10), [0, 2, 1, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(timeline.density(2), [3, 1]);
+        assert_eq!(timeline.density(1), [4]);
+    }
+}
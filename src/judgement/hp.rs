@@ -0,0 +1,387 @@
+use crate::models::{
+    osu_map::{HitObject, HitType, OsuMap},
+    osu_replay::OsuReplay,
+    spin::SpinProgress,
+};
+
+use super::{slider::CheckpointKind, HitResult, Judgement};
+
+const MAX_HP: f64 = 200.0;
+const HP_300: f64 = 6.0;
+const HP_100: f64 = 2.2;
+const HP_50: f64 = 0.4;
+/// Bonus at the end of a combo made only of 300s.
+const HP_GEKI: f64 = 14.0;
+/// Bonus at the end of a combo without 50s or misses.
+const HP_KATU: f64 = 10.0;
+const HP_SLIDER_TICK: f64 = 3.0;
+const HP_SLIDER_REPEAT: f64 = 4.0;
+const HP_SPINNER_SPIN: f64 = 1.7;
+/// The drain rate search gives up refining after this many attempts.
+const MAX_DRAIN_ATTEMPTS: usize = 1000;
+
+/// HP over the play, simulated from the judgements the way osu!stable drains and refills it.
+#[derive(Debug, Clone)]
+pub struct HpSimulation {
+    /// `(time in seconds, hp from 0 to 1)`, before and after every change.
+    pub(crate) points: Vec<(f64, f64)>,
+}
+
+/// How much HP the map drains and gives back, tuned per map like osu!stable does.
+#[derive(Debug, Clone, Copy)]
+struct DrainRate {
+    /// HP lost per millisecond outside of breaks.
+    passive_drain: f64,
+    normal_multiplier: f64,
+    combo_end_multiplier: f64,
+}
+
+impl HpSimulation {
+    pub fn simulate(beatmap: &OsuMap, replay: &OsuReplay, judgements: &[Judgement]) -> Self {
+        let Some(first) = beatmap.hit_objects.first() else {
+            return HpSimulation { points: Vec::new() };
+        };
+        let drain_rate = DrainRate::compute(beatmap);
+        let hp_drain_rate = beatmap.difficulty.hit_point_drain_rate;
+
+        // (time in ms, hp change)
+        let mut events = Vec::new();
+        let mut combo_results = Vec::new();
+        for (index, judgement) in judgements.iter().enumerate() {
+            let Some(object) = beatmap.hit_objects.get(judgement.hit_object_index) else {
+                continue;
+            };
+
+            if let HitType::Spinner(spinner) = &object.hit_type {
+                let progress = SpinProgress::compute(
+                    object.time as f64,
+                    spinner,
+                    &beatmap.difficulty,
+                    &replay.replay_data,
+                    object.end_time(),
+                );
+                events.push((
+                    judgement.time * 1000.0,
+                    progress.spins().floor() * HP_SPINNER_SPIN,
+                ));
+            }
+            if let Some(slider) = &judgement.slider {
+                for checkpoint in slider
+                    .checkpoints
+                    .iter()
+                    .filter(|checkpoint| checkpoint.hit)
+                {
+                    let change = match checkpoint.kind {
+                        CheckpointKind::Tick => HP_SLIDER_TICK,
+                        CheckpointKind::Repeat | CheckpointKind::End => HP_SLIDER_REPEAT,
+                    };
+                    events.push((checkpoint.time * 1000.0, change));
+                }
+            }
+
+            combo_results.push(judgement.result);
+            let is_combo_end = judgements
+                .get(index + 1)
+                .and_then(|next| beatmap.hit_objects.get(next.hit_object_index))
+                .is_none_or(|next| next.new_combo);
+
+            let change = match judgement.result {
+                HitResult::Miss => -difficulty_range(hp_drain_rate, 6.0, 25.0, 40.0),
+                result => {
+                    let mut change = match result {
+                        HitResult::Great => HP_300,
+                        HitResult::Ok => HP_100,
+                        _ => HP_50,
+                    } * drain_rate.normal_multiplier;
+                    if is_combo_end {
+                        if combo_results
+                            .iter()
+                            .all(|result| *result == HitResult::Great)
+                        {
+                            change += HP_GEKI * drain_rate.combo_end_multiplier;
+                        } else if combo_results
+                            .iter()
+                            .all(|result| matches!(result, HitResult::Great | HitResult::Ok))
+                        {
+                            change += HP_KATU * drain_rate.combo_end_multiplier;
+                        }
+                    }
+                    change
+                }
+            };
+            if is_combo_end {
+                combo_results.clear();
+            }
+            events.push((judgement.time * 1000.0, change));
+        }
+        events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut hp = MAX_HP;
+        let mut last_time = first.time as f64;
+        let mut points = vec![(last_time / 1000.0, 1.0)];
+        for (time, change) in events {
+            hp = (hp - drain_rate.passive_drain * drain_time(beatmap, last_time, time)).max(0.0);
+            points.push((time / 1000.0, hp / MAX_HP));
+            hp = (hp + change).clamp(0.0, MAX_HP);
+            points.push((time / 1000.0, hp / MAX_HP));
+            last_time = last_time.max(time);
+        }
+
+        HpSimulation { points }
+    }
+
+    /// HP from 0 to 1 at `time` in seconds.
+    pub fn at(&self, time: f64) -> f64 {
+        let index = self
+            .points
+            .partition_point(|(point_time, _)| *point_time <= time);
+        let previous = index.checked_sub(1).map(|index| self.points[index]);
+        match (previous, self.points.get(index).copied()) {
+            (Some((start_time, start)), Some((end_time, end))) if end_time > start_time => {
+                start + (end - start) * (time - start_time) / (end_time - start_time)
+            }
+            (Some((_, hp)), _) => hp,
+            (None, _) => 1.0,
+        }
+    }
+
+    /// The lowest HP of the play and when it happened, how close the player came to failing.
+    pub fn lowest(&self) -> Option<(f64, f64)> {
+        self.points
+            .iter()
+            .copied()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+impl DrainRate {
+    /// Finds the fastest drain a perfect play survives comfortably, like osu!stable.
+    fn compute(beatmap: &OsuMap) -> Self {
+        let hp_drain_rate = beatmap.difficulty.hit_point_drain_rate;
+        let lowest_hp_ever = difficulty_range(hp_drain_rate, 195.0, 160.0, 60.0);
+        let lowest_hp_combo_end = difficulty_range(hp_drain_rate, 198.0, 170.0, 80.0);
+        let lowest_hp_end = difficulty_range(hp_drain_rate, 198.0, 180.0, 80.0);
+        let hp_recovery_available = difficulty_range(hp_drain_rate, 8.0, 4.0, 0.0);
+
+        let mut rate = DrainRate {
+            passive_drain: 0.05,
+            normal_multiplier: 1.0,
+            combo_end_multiplier: 1.0,
+        };
+
+        let objects = &beatmap.hit_objects;
+        for _ in 0..MAX_DRAIN_ATTEMPTS {
+            fn increase(hp: &mut f64, hp_uncapped: &mut f64, amount: f64) {
+                *hp = (*hp + amount).clamp(0.0, MAX_HP);
+                *hp_uncapped = (*hp_uncapped + amount).max(0.0);
+            }
+
+            let mut hp = MAX_HP;
+            let mut hp_uncapped = MAX_HP;
+
+            let mut last_time =
+                objects[0].time as f64 - beatmap.difficulty.approach_rate.preempt * 1000.0;
+            let mut combo_too_low_count = 0;
+            let mut failed = false;
+
+            for (index, object) in objects.iter().enumerate() {
+                let start = object.time as f64;
+                let drain = rate.passive_drain * drain_time(beatmap, last_time, start);
+                increase(&mut hp, &mut hp_uncapped, -drain);
+                last_time = object.end_time();
+
+                if hp <= lowest_hp_ever {
+                    rate.passive_drain *= 0.96;
+                    failed = true;
+                    break;
+                }
+
+                let drain = rate.passive_drain * (object.end_time() - start);
+                increase(&mut hp, &mut hp_uncapped, -drain);
+                increase(&mut hp, &mut hp_uncapped, object_bonus(beatmap, object));
+
+                if objects.get(index + 1).is_none_or(|next| next.new_combo) {
+                    increase(
+                        &mut hp,
+                        &mut hp_uncapped,
+                        HP_GEKI * rate.combo_end_multiplier,
+                    );
+                    if hp < lowest_hp_combo_end {
+                        combo_too_low_count += 1;
+                        if combo_too_low_count > 2 {
+                            rate.combo_end_multiplier *= 1.07;
+                            rate.normal_multiplier *= 1.03;
+                            failed = true;
+                            break;
+                        }
+                    }
+                } else {
+                    increase(&mut hp, &mut hp_uncapped, HP_300 * rate.normal_multiplier);
+                }
+            }
+
+            if failed {
+                continue;
+            }
+            if hp < lowest_hp_end {
+                rate.passive_drain *= 0.94;
+                rate.combo_end_multiplier *= 1.01;
+                rate.normal_multiplier *= 1.01;
+                continue;
+            }
+            let recovery = (hp_uncapped - MAX_HP) / objects.len() as f64;
+            if recovery < hp_recovery_available {
+                rate.passive_drain *= 0.96;
+                rate.combo_end_multiplier *= 1.02;
+                rate.normal_multiplier *= 1.01;
+                continue;
+            }
+            break;
+        }
+        rate
+    }
+}
+
+/// HP a perfect play gets from an object's ticks, repeats and spins.
+fn object_bonus(beatmap: &OsuMap, object: &HitObject) -> f64 {
+    match &object.hit_type {
+        HitType::Circle => 0.0,
+        HitType::Slider(slider) => {
+            let ticks = (0..slider.repeat)
+                .map(|span| slider.span_ticks(span).len())
+                .sum::<usize>();
+            ticks as f64 * HP_SLIDER_TICK + slider.repeat as f64 * HP_SLIDER_REPEAT
+        }
+        HitType::Spinner(spinner) => {
+            let progress = SpinProgress::compute(
+                object.time as f64,
+                spinner,
+                &beatmap.difficulty,
+                &[],
+                object.time as f64,
+            );
+            progress.required_spins as f64 * HP_SPINNER_SPIN
+        }
+    }
+}
+
+/// Milliseconds between `start` and `end` that aren't part of a break.
+fn drain_time(beatmap: &OsuMap, start: f64, end: f64) -> f64 {
+    let in_breaks: f64 = beatmap
+        .events
+        .breaks
+        .iter()
+        .map(|b| {
+            let overlap_start = (b.start_time as f64).max(start);
+            let overlap_end = (b.end_time as f64).min(end);
+            (overlap_end - overlap_start).max(0.0)
+        })
+        .sum();
+    (end - start - in_breaks).max(0.0)
+}
+
+fn difficulty_range(difficulty: f64, min: f64, mid: f64, max: f64) -> f64 {
+    if difficulty > 5.0 {
+        mid + (max - mid) * (difficulty - 5.0) / 5.0
+    } else if difficulty < 5.0 {
+        mid - (mid - min) * (5.0 - difficulty) / 5.0
+    } else {
+        mid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judgement::tests::replay;
+
+    /// Circles half a second apart in a single combo.
+    fn beatmap(hp_drain_rate: f64) -> OsuMap {
+        format!(
+            "osu file format v14
+
+[Difficulty]
+HPDrainRate:{}
+
+[TimingPoints]
+0,500,4,2,0,60,1,0
+
+[HitObjects]
+100,100,1000,5,0
+100,100,1500,1,0
+100,100,2000,1,0
+100,100,2500,1,0
+100,100,3000,1,0
+100,100,3500,1,0
+",
+            hp_drain_rate
+        )
+        .parse()
+        .unwrap()
+    }
+
+    /// Judges each circle with `results` at its time.
+    fn simulate(beatmap: &OsuMap, results: &[HitResult]) -> HpSimulation {
+        let judgements: Vec<Judgement> = results
+            .iter()
+            .enumerate()
+            .map(|(index, result)| Judgement {
+                hit_object_index: index,
+                result: *result,
+                miss_reason: None,
+                hit_error: None,
+                time: beatmap.hit_objects[index].time as f64 / 1000.0,
+                cursor_position: (100.0, 100.0),
+                frame_index: 0,
+                slider: None,
+            })
+            .collect();
+        HpSimulation::simulate(beatmap, &replay(&[]), &judgements)
+    }
+
+    /// HP gained or lost by the judgement of circle `index`.
+    fn change(simulation: &HpSimulation, index: usize) -> f64 {
+        // the first point is the start, then each judgement has one before and after
+        let (before, after) = (
+            simulation.points[1 + index * 2],
+            simulation.points[2 + index * 2],
+        );
+        after.1 - before.1
+    }
+
+    #[test]
+    fn drains_more_for_misses_on_higher_hp_drain_rates() {
+        use HitResult::Miss;
+        for (hp_drain_rate, drained) in [(0.0, 6.0), (5.0, 25.0), (10.0, 40.0)] {
+            let simulation = simulate(&beatmap(hp_drain_rate), &[Miss, Miss]);
+            assert!((change(&simulation, 1) + drained / MAX_HP).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn recovers_by_result() {
+        use HitResult::*;
+        let beatmap = beatmap(5.0);
+        // miss first so that there is room to recover
+        let great = change(&simulate(&beatmap, &[Miss, Great]), 1);
+        let ok = change(&simulate(&beatmap, &[Miss, Ok]), 1);
+        let meh = change(&simulate(&beatmap, &[Miss, Meh]), 1);
+        assert!(great > 0.0);
+        assert!((great / ok - HP_300 / HP_100).abs() < 1e-9);
+        assert!((great / meh - HP_300 / HP_50).abs() < 1e-9);
+    }
+
+    #[test]
+    fn keeps_hp_between_empty_and_full() {
+        use HitResult::*;
+        let failed = simulate(&beatmap(10.0), &[Miss; 6]);
+        assert!(failed.points.iter().all(|(_, hp)| *hp >= 0.0));
+        assert_eq!(failed.lowest().unwrap().1, 0.0);
+        assert_eq!(failed.at(3.5), 0.0);
+
+        let perfect = simulate(&beatmap(0.0), &[Great; 6]);
+        assert!(perfect.points.iter().all(|(_, hp)| *hp <= 1.0));
+        assert_eq!(perfect.at(0.0), 1.0);
+        assert_eq!(perfect.at(3.5), 1.0);
+    }
+}
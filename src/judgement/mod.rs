@@ -0,0 +1,652 @@
+pub mod hp;
+pub mod score;
+pub mod slider;
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::models::{
+    osu_map::{HitObject, HitType, OsuMap},
+    osu_replay::{Keys, OsuReplay, ReplayData},
+    spin::SpinProgress,
+};
+
+use self::slider::SliderJudgement;
+
+/// Pressing earlier than this before an object shakes it instead of missing it, in milliseconds.
+const MISS_WINDOW: f64 = 400.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitResult {
+    Great,
+    Ok,
+    Meh,
+    Miss,
+}
+
+impl Display for HitResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            HitResult::Great => write!(f, "300"),
+            HitResult::Ok => write!(f, "100"),
+            HitResult::Meh => write!(f, "50"),
+            HitResult::Miss => write!(f, "miss"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissReason {
+    /// Pressed in time but outside of the circle.
+    Aim,
+    /// Pressed on the circle but too early.
+    Timing,
+    /// Pressed on the circle while an earlier object was still waiting to be hit.
+    Notelock,
+    /// Never pressed.
+    Timeout,
+}
+
+#[derive(Debug, Clone)]
+pub struct Judgement {
+    pub(crate) hit_object_index: usize,
+    pub(crate) result: HitResult,
+    pub(crate) miss_reason: Option<MissReason>,
+    /// Seconds between the object's time and the press, negative when early.
+    /// For sliders this is the head's.
+    pub(crate) hit_error: Option<f64>,
+    /// Seconds, when the judgement happened. Sliders are judged at their end.
+    pub(crate) time: f64,
+    /// Cursor position of the press or timeout, the head's for sliders.
+    pub(crate) cursor_position: (f64, f64),
+    pub(crate) frame_index: usize,
+    pub(crate) slider: Option<SliderJudgement>,
+}
+
+/// Number of objects per result.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HitCounts {
+    pub(crate) count_300: u32,
+    pub(crate) count_100: u32,
+    pub(crate) count_50: u32,
+    pub(crate) count_miss: u32,
+}
+
+impl HitCounts {
+    pub fn from_judgements(judgements: &[Judgement]) -> Self {
+        let mut counts = HitCounts::default();
+        for judgement in judgements {
+            match judgement.result {
+                HitResult::Great => counts.count_300 += 1,
+                HitResult::Ok => counts.count_100 += 1,
+                HitResult::Meh => counts.count_50 += 1,
+                HitResult::Miss => counts.count_miss += 1,
+            }
+        }
+        counts
+    }
+
+    /// Counts the replay's header says the play got.
+    pub fn from_replay(replay: &OsuReplay) -> Self {
+        HitCounts {
+            count_300: replay.count_300 as u32,
+            count_100: replay.count_100 as u32,
+            count_50: replay.count_50 as u32,
+            count_miss: replay.count_miss as u32,
+        }
+    }
+
+    pub fn count(&self, result: HitResult) -> u32 {
+        match result {
+            HitResult::Great => self.count_300,
+            HitResult::Ok => self.count_100,
+            HitResult::Meh => self.count_50,
+            HitResult::Miss => self.count_miss,
+        }
+    }
+}
+
+/// Simulated counts compared against the replay's header.
+#[derive(Debug, Clone, Copy)]
+pub struct JudgementReport {
+    pub(crate) simulated: HitCounts,
+    pub(crate) header: HitCounts,
+}
+
+impl JudgementReport {
+    pub fn new(judgements: &[Judgement], replay: &OsuReplay) -> Self {
+        JudgementReport {
+            simulated: HitCounts::from_judgements(judgements),
+            header: HitCounts::from_replay(replay),
+        }
+    }
+
+    /// Results whose simulated count differs from the header, with (simulated, header) counts.
+    pub fn mismatches(&self) -> Vec<(HitResult, u32, u32)> {
+        [
+            HitResult::Great,
+            HitResult::Ok,
+            HitResult::Meh,
+            HitResult::Miss,
+        ]
+        .into_iter()
+        .map(|result| {
+            (
+                result,
+                self.simulated.count(result),
+                self.header.count(result),
+            )
+        })
+        .filter(|(_, simulated, header)| simulated != header)
+        .collect()
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.simulated == self.header
+    }
+}
+
+impl Display for JudgementReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_match() {
+            return write!(f, "Judgements match the replay");
+        }
+
+        let mismatches = self
+            .mismatches()
+            .into_iter()
+            .map(|(result, simulated, header)| {
+                format!("{}: simulated {}, replay {}", result, simulated, header)
+            })
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "Judgements differ from the replay ({})",
+            mismatches.join(", ")
+        )
+    }
+}
+
+/// Plays the replay against the beatmap and judges every hit object.
+///
+/// The result has one entry per hit object, in the beatmap's order.
+pub fn judge(beatmap: &OsuMap, replay: &OsuReplay) -> Vec<Judgement> {
+    let mut judge = Judge {
+        beatmap,
+        frames: &replay.replay_data,
+        judgements: vec![None; beatmap.hit_objects.len()],
+        failed_presses: vec![None; beatmap.hit_objects.len()],
+        next: 0,
+    };
+
+    for frame_index in 0..replay.replay_data.len() {
+        judge.process_frame(frame_index);
+    }
+    judge.finish()
+}
+
+struct Judge<'a> {
+    beatmap: &'a OsuMap,
+    frames: &'a [ReplayData],
+    judgements: Vec<Option<Judgement>>,
+    /// Frame of the last press that didn't count for an object, reported if it times out.
+    failed_presses: Vec<Option<(usize, MissReason)>>,
+    /// First hit object without a judgement.
+    next: usize,
+}
+
+impl Judge<'_> {
+    fn process_frame(&mut self, frame_index: usize) {
+        let time = self.frames[frame_index].total_time as f64;
+        self.expire(frame_index, time);
+
+        if self.is_pressed(frame_index) {
+            self.press(frame_index, time);
+        }
+    }
+
+    /// Misses circles and sliders whose hit window is over, and judges finished spinners.
+    fn expire(&mut self, frame_index: usize, time: f64) {
+        let hit_window_50 = self.beatmap.difficulty.overall_difficulty.hit_window_50 * 1000.0;
+
+        for index in self.next..self.beatmap.hit_objects.len() {
+            let object = &self.beatmap.hit_objects[index];
+            if object.time as f64 - hit_window_50 > time {
+                break;
+            }
+            if self.judgements[index].is_some() {
+                continue;
+            }
+
+            match object.hit_type {
+                HitType::Spinner(_) if time >= object.end_time() => {
+                    self.judge_spinner(index, frame_index)
+                }
+                HitType::Circle | HitType::Slider(_)
+                    if time > object.time as f64 + hit_window_50 =>
+                {
+                    self.miss_timeout(index, frame_index)
+                }
+                _ => {}
+            }
+        }
+        self.advance();
+    }
+
+    /// Judges the first object under the cursor, the way osu!stable handles a click.
+    fn press(&mut self, frame_index: usize, time: f64) {
+        let frame = &self.frames[frame_index];
+        let difficulty = &self.beatmap.difficulty;
+        let hit_window_50 = difficulty.overall_difficulty.hit_window_50 * 1000.0;
+        let radius = difficulty.circle_radius();
+
+        let candidates = (self.next..self.beatmap.hit_objects.len())
+            .take_while(|index| self.beatmap.hit_objects[*index].time as f64 - MISS_WINDOW <= time)
+            .filter(|index| {
+                self.judgements[*index].is_none()
+                    && !matches!(
+                        self.beatmap.hit_objects[*index].hit_type,
+                        HitType::Spinner(_)
+                    )
+            })
+            .collect::<Vec<_>>();
+
+        let Some(&index) = candidates
+            .iter()
+            .find(|index| distance(frame, &self.beatmap.hit_objects[**index]) <= radius)
+        else {
+            // might still be hit by a later press, otherwise it times out as an aim miss
+            if let Some(&index) = candidates.first() {
+                let hit_error = time - self.beatmap.hit_objects[index].time as f64;
+                if hit_error.abs() <= hit_window_50 {
+                    self.failed_presses[index] = Some((frame_index, MissReason::Aim));
+                }
+            }
+            return;
+        };
+
+        // notelock: an earlier object that hasn't started yet blocks the click
+        let is_locked = candidates
+            .iter()
+            .take_while(|earlier| **earlier < index)
+            .any(|earlier| self.beatmap.hit_objects[*earlier].time as f64 > time);
+        if is_locked {
+            self.failed_presses[index] = Some((frame_index, MissReason::Notelock));
+            return;
+        }
+
+        let hit_error = time - self.beatmap.hit_objects[index].time as f64;
+        if hit_error.abs() <= hit_window_50 {
+            self.hit(index, frame_index, hit_error);
+        } else {
+            self.judge(
+                index,
+                frame_index,
+                HitResult::Miss,
+                Some(MissReason::Timing),
+                Some(hit_error),
+            );
+        }
+
+        // hitting an object gives up on every earlier one
+        for earlier in candidates
+            .into_iter()
+            .take_while(|earlier| *earlier < index)
+        {
+            self.miss_timeout(earlier, frame_index);
+        }
+        self.advance();
+    }
+
+    fn hit(&mut self, index: usize, frame_index: usize, hit_error: f64) {
+        let overall_difficulty = &self.beatmap.difficulty.overall_difficulty;
+        let error = hit_error.abs() / 1000.0;
+        let result = if error <= overall_difficulty.hit_window_300 {
+            HitResult::Great
+        } else if error <= overall_difficulty.hit_window_100 {
+            HitResult::Ok
+        } else {
+            HitResult::Meh
+        };
+        self.judge(index, frame_index, result, None, Some(hit_error));
+    }
+
+    fn miss_timeout(&mut self, index: usize, frame_index: usize) {
+        match self.failed_presses[index] {
+            Some((press_frame_index, reason)) => {
+                let hit_error = self.frames[press_frame_index].total_time as f64
+                    - self.beatmap.hit_objects[index].time as f64;
+                self.judge(
+                    index,
+                    press_frame_index,
+                    HitResult::Miss,
+                    Some(reason),
+                    Some(hit_error),
+                );
+            }
+            None => self.judge(
+                index,
+                frame_index,
+                HitResult::Miss,
+                Some(MissReason::Timeout),
+                None,
+            ),
+        }
+    }
+
+    fn judge_spinner(&mut self, index: usize, frame_index: usize) {
+        let object = &self.beatmap.hit_objects[index];
+        let HitType::Spinner(spinner) = &object.hit_type else {
+            return;
+        };
+
+        let progress = SpinProgress::compute(
+            object.time as f64,
+            spinner,
+            &self.beatmap.difficulty,
+            &self.frames[..=frame_index],
+            object.end_time(),
+        );
+        let completion = progress.completion();
+        let result = if completion >= 1.0 {
+            HitResult::Great
+        } else if completion > 0.9 {
+            HitResult::Ok
+        } else if completion > 0.75 {
+            HitResult::Meh
+        } else {
+            HitResult::Miss
+        };
+        let miss_reason = (result == HitResult::Miss).then_some(MissReason::Timeout);
+        self.judge(index, frame_index, result, miss_reason, None);
+    }
+
+    fn judge(
+        &mut self,
+        index: usize,
+        frame_index: usize,
+        result: HitResult,
+        miss_reason: Option<MissReason>,
+        hit_error: Option<f64>,
+    ) {
+        let frame = &self.frames[frame_index];
+        self.judgements[index] = Some(Judgement {
+            hit_object_index: index,
+            result,
+            miss_reason,
+            hit_error: hit_error.map(|error| error / 1000.0),
+            time: frame.total_time as f64 / 1000.0,
+            cursor_position: (frame.x as f64, frame.y as f64),
+            frame_index,
+            slider: None,
+        });
+    }
+
+    fn advance(&mut self) {
+        while self
+            .judgements
+            .get(self.next)
+            .is_some_and(|judgement| judgement.is_some())
+        {
+            self.next += 1;
+        }
+    }
+
+    /// Whether the left or right button went down on this frame.
+    ///
+    /// K1 and K2 also set M1 and M2, so a key and its mouse button count as one button.
+    fn is_pressed(&self, frame_index: usize) -> bool {
+        fn buttons(keys: i32) -> [bool; 2] {
+            [
+                keys & (Keys::M1 as i32 | Keys::K1 as i32) != 0,
+                keys & (Keys::M2 as i32 | Keys::K2 as i32) != 0,
+            ]
+        }
+
+        let keys = buttons(self.frames[frame_index].keys);
+        let last_keys = buttons(
+            frame_index
+                .checked_sub(1)
+                .map_or(0, |last_index| self.frames[last_index].keys),
+        );
+
+        keys.iter()
+            .zip(last_keys)
+            .any(|(is_down, was_down)| *is_down && !was_down)
+    }
+
+    /// Objects left without a judgement once the replay is over were never hit.
+    fn finish(mut self) -> Vec<Judgement> {
+        if let Some(last_frame_index) = self.frames.len().checked_sub(1) {
+            for index in self.next..self.beatmap.hit_objects.len() {
+                if self.judgements[index].is_some() {
+                    continue;
+                }
+                match self.beatmap.hit_objects[index].hit_type {
+                    HitType::Spinner(_) => self.judge_spinner(index, last_frame_index),
+                    _ => self.miss_timeout(index, last_frame_index),
+                }
+            }
+        }
+
+        // so far sliders were judged on their head only
+        let radius = self.beatmap.difficulty.circle_radius();
+        for (object, judgement) in self.beatmap.hit_objects.iter().zip(&mut self.judgements) {
+            let (HitType::Slider(slider), Some(judgement)) = (&object.hit_type, judgement) else {
+                continue;
+            };
+
+            let slider_judgement = SliderJudgement::compute(
+                object,
+                slider,
+                radius,
+                self.frames,
+                judgement.result != HitResult::Miss,
+            );
+            judgement.result = slider_judgement.result();
+            if judgement.result != HitResult::Miss {
+                judgement.miss_reason = None;
+            }
+            judgement.time = object.end_time() / 1000.0;
+            judgement.slider = Some(slider_judgement);
+        }
+
+        // without a single frame there is nothing to judge against, every object is missed
+        self.judgements
+            .into_iter()
+            .zip(&self.beatmap.hit_objects)
+            .enumerate()
+            .map(|(index, (judgement, object))| {
+                judgement.unwrap_or_else(|| Judgement {
+                    hit_object_index: index,
+                    result: HitResult::Miss,
+                    miss_reason: Some(MissReason::Timeout),
+                    hit_error: None,
+                    time: object.end_time() / 1000.0,
+                    cursor_position: (0.0, 0.0),
+                    frame_index: 0,
+                    slider: None,
+                })
+            })
+            .collect()
+    }
+}
+
+fn distance(frame: &ReplayData, object: &HitObject) -> f64 {
+    let (x, y) = object.position();
+    ((frame.x as f64 - x).powi(2) + (frame.y as f64 - y).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::models::{mods::Mods, osu_replay::Gamemode};
+
+    // OD 5 gives hit windows of 50, 100 and 150 ms; the slider takes a second and has a
+    // tick halfway
+    const MAP: &str = "osu file format v14
+
+[Difficulty]
+HPDrainRate:5
+CircleSize:4
+OverallDifficulty:5
+ApproachRate:5
+SliderMultiplier:1
+SliderTickRate:1
+
+[TimingPoints]
+0,500,4,2,0,60,1,0
+
+[HitObjects]
+100,100,1000,1,0,0:0:0:0:
+300,100,1200,1,0,0:0:0:0:
+100,300,3000,1,0,0:0:0:0:
+300,300,5000,2,0,L|500:300,1,200
+";
+
+    const K1: i32 = Keys::M1 as i32 | Keys::K1 as i32;
+
+    /// Frames as `(time, x, y, keys)`, with the time since the start of the replay.
+    pub(crate) fn replay(frames: &[(u64, f32, f32, i32)]) -> OsuReplay {
+        let mut last_time = 0;
+        let replay_data = frames
+            .iter()
+            .map(|&(total_time, x, y, keys)| {
+                let time = (total_time - last_time) as i64;
+                last_time = total_time;
+                ReplayData {
+                    time,
+                    x,
+                    y,
+                    keys,
+                    total_time,
+                }
+            })
+            .collect();
+
+        OsuReplay {
+            gamemode: Gamemode::Standard,
+            version: 20240101,
+            beatmap_hash: None,
+            player_name: None,
+            replay_hash: None,
+            count_300: 0,
+            count_100: 0,
+            count_50: 0,
+            count_geki: 0,
+            count_katu: 0,
+            count_miss: 0,
+            score: 0,
+            max_combo: 0,
+            is_perfect_combo: false,
+            mods: Mods::default(),
+            life_bar_graph: None,
+            life_bar: Vec::new(),
+            timestamp: 0,
+            online_score_id: 0,
+            additional_mod_info: None,
+            replay_data,
+            rng_seed: None,
+            replay_data_compressed: Vec::new(),
+        }
+    }
+
+    fn judge_frames(frames: &[(u64, f32, f32, i32)]) -> Vec<Judgement> {
+        let beatmap: OsuMap = MAP.parse().unwrap();
+        let judgements = judge(&beatmap, &replay(frames));
+        assert_eq!(judgements.len(), beatmap.hit_objects.len());
+        judgements
+    }
+
+    #[test]
+    fn judges_circle() {
+        let judgements = judge_frames(&[
+            (0, 0.0, 0.0, 0),
+            (1070, 100.0, 100.0, K1),
+            (1080, 100.0, 100.0, 0),
+            (7000, 0.0, 0.0, 0),
+        ]);
+
+        let circle = &judgements[0];
+        assert_eq!(circle.result, HitResult::Ok);
+        assert_eq!(circle.miss_reason, None);
+        assert_eq!(circle.hit_error, Some(0.07));
+        assert_eq!(circle.frame_index, 1);
+    }
+
+    #[test]
+    fn judges_notelock() {
+        // the second circle is clicked before the first one has even started
+        let judgements = judge_frames(&[
+            (0, 0.0, 0.0, 0),
+            (900, 300.0, 100.0, K1),
+            (910, 300.0, 100.0, 0),
+            (7000, 0.0, 0.0, 0),
+        ]);
+
+        let locked = &judgements[1];
+        assert_eq!(locked.result, HitResult::Miss);
+        assert_eq!(locked.miss_reason, Some(MissReason::Notelock));
+        assert_eq!(locked.hit_error, Some(-0.3));
+        assert_eq!(locked.frame_index, 1);
+        assert_eq!(judgements[0].miss_reason, Some(MissReason::Timeout));
+    }
+
+    #[test]
+    fn judges_timeout() {
+        // pressing more than 400 ms early doesn't count, so the circle times out
+        let judgements = judge_frames(&[
+            (0, 0.0, 0.0, 0),
+            (2500, 100.0, 300.0, K1),
+            (2510, 100.0, 300.0, 0),
+            (3100, 100.0, 300.0, 0),
+            (3200, 100.0, 300.0, 0),
+            (7000, 0.0, 0.0, 0),
+        ]);
+
+        let circle = &judgements[2];
+        assert_eq!(circle.result, HitResult::Miss);
+        assert_eq!(circle.miss_reason, Some(MissReason::Timeout));
+        assert_eq!(circle.hit_error, None);
+        assert_eq!(circle.frame_index, 4);
+    }
+
+    #[test]
+    fn judges_slider_with_dropped_tick() {
+        // let go over the tick at 5500 ms and hold again for the end
+        let judgements = judge_frames(&[
+            (0, 0.0, 0.0, 0),
+            (5000, 300.0, 300.0, K1),
+            (5250, 350.0, 300.0, K1),
+            (5450, 390.0, 300.0, 0),
+            (5600, 420.0, 300.0, K1),
+            (5900, 490.0, 300.0, K1),
+            (6100, 500.0, 300.0, 0),
+            (7000, 0.0, 0.0, 0),
+        ]);
+
+        let slider = &judgements[3];
+        assert_eq!(slider.result, HitResult::Ok);
+        assert_eq!(slider.miss_reason, None);
+        assert_eq!(slider.hit_error, Some(0.0));
+        assert_eq!(slider.frame_index, 1);
+        assert_eq!(slider.time, 6.0);
+
+        let slider = slider.slider.as_ref().unwrap();
+        assert!(slider.head_hit);
+        let missed = slider.missed_ticks().collect::<Vec<_>>();
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].time, 5.5);
+        assert_eq!(missed[0].frame_index, Some(3));
+        assert!(slider.is_break());
+    }
+
+    #[test]
+    fn judges_every_object_without_frames() {
+        let judgements = judge_frames(&[]);
+        assert!(judgements
+            .iter()
+            .enumerate()
+            .all(|(index, judgement)| judgement.hit_object_index == index
+                && judgement.result == HitResult::Miss));
+    }
+}
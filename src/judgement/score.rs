@@ -0,0 +1,385 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::models::{
+    mods::Mods,
+    osu_map::{HitType, OsuMap},
+    osu_replay::OsuReplay,
+    spin::SpinProgress,
+};
+
+use super::{slider::CheckpointKind, HitResult, Judgement};
+
+const SLIDER_TICK_SCORE: u64 = 10;
+const SLIDER_EDGE_SCORE: u64 = 30;
+const SPIN_SCORE: u64 = 100;
+const BONUS_SPIN_SCORE: u64 = 1000;
+
+/// Score multipliers of the mods that change ScoreV1.
+const MOD_MULTIPLIERS: [(Mods, f64); 8] = [
+    (Mods::NO_FAIL, 0.5),
+    (Mods::EASY, 0.5),
+    (Mods::HIDDEN, 1.06),
+    (Mods::HARD_ROCK, 1.06),
+    (Mods::DOUBLE_TIME, 1.12),
+    (Mods::HALF_TIME, 0.3),
+    (Mods::FLASHLIGHT, 1.12),
+    (Mods::SPUN_OUT, 0.9),
+];
+
+/// Combo and score right after a scoring event.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScoreState {
+    /// Seconds.
+    pub(crate) time: f64,
+    pub(crate) combo: u32,
+    pub(crate) max_combo: u32,
+    pub(crate) score: u64,
+}
+
+/// ScoreV1 and combo of a play, replayed from its judgements.
+#[derive(Debug, Clone)]
+pub struct ScoreSimulation {
+    /// One state per scoring event, in time order.
+    pub(crate) states: Vec<ScoreState>,
+    /// Whether the combo never broke and no slider end was dropped.
+    pub(crate) is_perfect: bool,
+}
+
+enum ScoreEvent {
+    /// A judged circle, slider or spinner, worth its result with the combo multiplier.
+    /// Sliders add combo through their head and checkpoints instead.
+    Object { result: HitResult, adds_combo: bool },
+    /// A slider head, tick, repeat or end, worth a flat amount.
+    SliderCheckpoint {
+        score: u64,
+        hit: bool,
+        breaks_combo: bool,
+    },
+    /// Spinner rotations, worth a flat amount without combo.
+    Spins(u64),
+}
+
+impl ScoreSimulation {
+    /// `difficulty_multiplier` comes from the map before mods were applied, since
+    /// HR and EZ don't change it.
+    pub fn simulate(
+        beatmap: &OsuMap,
+        replay: &OsuReplay,
+        judgements: &[Judgement],
+        difficulty_multiplier: f64,
+    ) -> Self {
+        let multiplier = difficulty_multiplier * mod_multiplier(replay.mods);
+
+        let mut events = Vec::new();
+        for judgement in judgements {
+            let Some(object) = beatmap.hit_objects.get(judgement.hit_object_index) else {
+                continue;
+            };
+
+            match &object.hit_type {
+                HitType::Circle => events.push((
+                    judgement.time,
+                    ScoreEvent::Object {
+                        result: judgement.result,
+                        adds_combo: true,
+                    },
+                )),
+                HitType::Slider(_) => {
+                    if let Some(slider) = &judgement.slider {
+                        events.push((
+                            object.time as f64 / 1000.0,
+                            ScoreEvent::SliderCheckpoint {
+                                score: SLIDER_EDGE_SCORE,
+                                hit: slider.head_hit,
+                                breaks_combo: true,
+                            },
+                        ));
+                        for checkpoint in &slider.checkpoints {
+                            let (score, breaks_combo) = match checkpoint.kind {
+                                CheckpointKind::Tick => (SLIDER_TICK_SCORE, true),
+                                CheckpointKind::Repeat => (SLIDER_EDGE_SCORE, true),
+                                CheckpointKind::End => (SLIDER_EDGE_SCORE, false),
+                            };
+                            events.push((
+                                checkpoint.time,
+                                ScoreEvent::SliderCheckpoint {
+                                    score,
+                                    hit: checkpoint.hit,
+                                    breaks_combo,
+                                },
+                            ));
+                        }
+                    }
+                    events.push((
+                        judgement.time,
+                        ScoreEvent::Object {
+                            result: judgement.result,
+                            adds_combo: false,
+                        },
+                    ));
+                }
+                HitType::Spinner(spinner) => {
+                    let progress = SpinProgress::compute(
+                        object.time as f64,
+                        spinner,
+                        &beatmap.difficulty,
+                        &replay.replay_data,
+                        object.end_time(),
+                    );
+                    let bonus_spins = progress.bonus_spins() as u64;
+                    let spins = (progress.spins() as u64).saturating_sub(bonus_spins);
+                    events.push((
+                        judgement.time,
+                        ScoreEvent::Spins(spins * SPIN_SCORE + bonus_spins * BONUS_SPIN_SCORE),
+                    ));
+                    events.push((
+                        judgement.time,
+                        ScoreEvent::Object {
+                            result: judgement.result,
+                            adds_combo: true,
+                        },
+                    ));
+                }
+            }
+        }
+        events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut state = ScoreState::default();
+        let mut is_perfect = true;
+        let mut states = Vec::with_capacity(events.len());
+        for (time, event) in events {
+            state.time = time;
+            match event {
+                ScoreEvent::Object { result, .. } => {
+                    let hit_value: u64 = match result {
+                        HitResult::Great => 300,
+                        HitResult::Ok => 100,
+                        HitResult::Meh => 50,
+                        HitResult::Miss => 0,
+                    };
+                    let combo_multiplier = state.combo.saturating_sub(1) as f64;
+                    state.score += hit_value
+                        + (combo_multiplier * (hit_value as f64 / 25.0 * multiplier)) as u64;
+                }
+                ScoreEvent::SliderCheckpoint { score, hit, .. } if hit => state.score += score,
+                ScoreEvent::SliderCheckpoint { .. } => {}
+                ScoreEvent::Spins(score) => state.score += score,
+            }
+
+            let combo_change = match event {
+                ScoreEvent::Object {
+                    result: HitResult::Miss,
+                    ..
+                } => Some(false),
+                ScoreEvent::Object {
+                    adds_combo: true, ..
+                } => Some(true),
+                ScoreEvent::SliderCheckpoint { hit: true, .. } => Some(true),
+                ScoreEvent::SliderCheckpoint {
+                    hit: false,
+                    breaks_combo: true,
+                    ..
+                } => Some(false),
+                _ => None,
+            };
+            match combo_change {
+                Some(true) => state.combo += 1,
+                Some(false) => {
+                    is_perfect = false;
+                    state.combo = 0;
+                }
+                None => {}
+            }
+            // a dropped slider end keeps the combo but still isn't a perfect play
+            if let ScoreEvent::SliderCheckpoint { hit: false, .. } = event {
+                is_perfect = false;
+            }
+            state.max_combo = state.max_combo.max(state.combo);
+            states.push(state);
+        }
+
+        ScoreSimulation { states, is_perfect }
+    }
+
+    /// Combo and score at `time` in seconds.
+    pub fn at(&self, time: f64) -> ScoreState {
+        let index = self.states.partition_point(|state| state.time <= time);
+        index
+            .checked_sub(1)
+            .map_or_else(ScoreState::default, |index| self.states[index])
+    }
+
+    pub fn score(&self) -> u64 {
+        self.states.last().map_or(0, |state| state.score)
+    }
+
+    pub fn max_combo(&self) -> u32 {
+        self.states.last().map_or(0, |state| state.max_combo)
+    }
+}
+
+/// osu!stable's difficulty multiplier, from the map's HP, CS, OD and note density.
+pub fn difficulty_multiplier(beatmap: &OsuMap) -> f64 {
+    let difficulty = &beatmap.difficulty;
+    let break_length: u64 = beatmap
+        .events
+        .breaks
+        .iter()
+        .map(|b| b.end_time.saturating_sub(b.start_time))
+        .sum();
+    let drain_seconds = match (beatmap.hit_objects.first(), beatmap.hit_objects.last()) {
+        // whole seconds, cut down like stable does
+        (Some(first), Some(last)) => {
+            (last
+                .time
+                .saturating_sub(first.time)
+                .saturating_sub(break_length)
+                / 1000) as f64
+        }
+        _ => 0.0,
+    };
+    let object_to_drain_ratio = if drain_seconds == 0.0 {
+        16.0
+    } else {
+        (beatmap.hit_objects.len() as f64 / drain_seconds * 8.0).clamp(0.0, 16.0)
+    };
+
+    ((difficulty.hit_point_drain_rate
+        + difficulty.circle_size
+        + difficulty.overall_difficulty.value
+        + object_to_drain_ratio)
+        / 38.0
+        * 5.0)
+        .round()
+}
+
+pub fn mod_multiplier(mods: Mods) -> f64 {
+    MOD_MULTIPLIERS
+        .iter()
+        .filter(|(mod_, _)| mods.contains(*mod_))
+        .map(|(_, multiplier)| multiplier)
+        .product()
+}
+
+/// Simulated score and combo compared against the replay's header.
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreReport {
+    pub(crate) score: (u64, u64),
+    pub(crate) max_combo: (u32, u32),
+    pub(crate) is_perfect: (bool, bool),
+}
+
+impl ScoreReport {
+    pub fn new(simulation: &ScoreSimulation, replay: &OsuReplay) -> Self {
+        ScoreReport {
+            score: (simulation.score(), replay.score as u64),
+            max_combo: (simulation.max_combo(), replay.max_combo as u32),
+            is_perfect: (simulation.is_perfect, replay.is_perfect_combo),
+        }
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.score.0 == self.score.1
+            && self.max_combo.0 == self.max_combo.1
+            && self.is_perfect.0 == self.is_perfect.1
+    }
+}
+
+impl Display for ScoreReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.is_match() {
+            return write!(f, "Score and combo match the replay");
+        }
+
+        let mut mismatches = Vec::new();
+        if self.score.0 != self.score.1 {
+            mismatches.push(format!(
+                "score: simulated {}, replay {}",
+                self.score.0, self.score.1
+            ));
+        }
+        if self.max_combo.0 != self.max_combo.1 {
+            mismatches.push(format!(
+                "max combo: simulated {}, replay {}",
+                self.max_combo.0, self.max_combo.1
+            ));
+        }
+        if self.is_perfect.0 != self.is_perfect.1 {
+            mismatches.push(format!(
+                "perfect: simulated {}, replay {}",
+                self.is_perfect.0, self.is_perfect.1
+            ));
+        }
+        write!(
+            f,
+            "Score differs from the replay ({})",
+            mismatches.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judgement::{judge, tests::replay};
+
+    const MAP: &str = "osu file format v14
+
+[Difficulty]
+HPDrainRate:6
+CircleSize:4
+OverallDifficulty:6
+ApproachRate:5
+SliderMultiplier:1
+SliderTickRate:1
+
+[TimingPoints]
+0,500,4,2,0,60,1,0
+
+[HitObjects]
+100,100,1000,1,0,0:0:0:0:
+300,300,2000,2,0,L|500:300,1,200
+100,100,3000,1,0,0:0:0:0:
+";
+
+    #[test]
+    fn keeps_fractional_object_density() {
+        // three objects over two seconds are 12 towards the multiplier, not 8
+        let beatmap: OsuMap = MAP.parse().unwrap();
+        assert_eq!(difficulty_multiplier(&beatmap), 4.0);
+    }
+
+    #[test]
+    fn cuts_drain_time_to_whole_seconds() {
+        // 2.6 s of drain count as 2 s, for 12 towards the multiplier rather than 8
+        let beatmap: OsuMap = MAP
+            .replace("100,100,3000,1,0", "100,100,3600,1,0")
+            .parse()
+            .unwrap();
+        assert_eq!(difficulty_multiplier(&beatmap), 4.0);
+    }
+
+    #[test]
+    fn dropped_slider_end_is_not_perfect() {
+        let beatmap: OsuMap = MAP.parse().unwrap();
+        // follow the slider past its tick and let go before the end
+        let replay = replay(&[
+            (0, 0.0, 0.0, 0),
+            (1000, 100.0, 100.0, 1),
+            (1010, 100.0, 100.0, 0),
+            (2000, 300.0, 300.0, 1),
+            (2300, 360.0, 300.0, 1),
+            (2600, 420.0, 300.0, 1),
+            (2700, 440.0, 300.0, 0),
+            (3000, 100.0, 100.0, 2),
+            (3010, 100.0, 100.0, 0),
+            (4000, 0.0, 0.0, 0),
+        ]);
+        let judgements = judge(&beatmap, &replay);
+        let simulation = ScoreSimulation::simulate(&beatmap, &replay, &judgements, 4.0);
+
+        assert_eq!(judgements[1].result, HitResult::Ok);
+        assert_eq!(simulation.max_combo(), 4);
+        assert!(!simulation.is_perfect);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::models::{
+    osu_map::{HitObject, Slider},
+    osu_replay::ReplayData,
+};
+
+use super::HitResult;
+
+/// The follow circle is this much larger than the hit circle while tracking.
+const FOLLOW_RADIUS_SCALE: f64 = 2.4;
+/// osu!stable checks the slider end this long before the slider is over, in milliseconds.
+const END_LENIENCY: f64 = 36.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointKind {
+    Tick,
+    Repeat,
+    End,
+}
+
+/// A point of the slider at which the cursor has to be inside the follow circle.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub(crate) kind: CheckpointKind,
+    /// Seconds.
+    pub(crate) time: f64,
+    pub(crate) position: (f64, f64),
+    pub(crate) hit: bool,
+    /// Frame the cursor was checked on, none before the replay's first frame.
+    pub(crate) frame_index: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SliderJudgement {
+    pub(crate) head_hit: bool,
+    /// Ticks, repeats and the end in the order the slider ball reaches them.
+    pub(crate) checkpoints: Vec<Checkpoint>,
+}
+
+impl SliderJudgement {
+    pub fn compute(
+        object: &HitObject,
+        slider: &Slider,
+        circle_radius: f64,
+        frames: &[ReplayData],
+        head_hit: bool,
+    ) -> Self {
+        let start = object.time as f64;
+        let duration = object.end_time() - start;
+
+        let mut checkpoints = Vec::new();
+        for span in 0..slider.repeat {
+            for (time, _) in slider.span_ticks(span) {
+                checkpoints.push((CheckpointKind::Tick, start + time));
+            }
+            if span + 1 < slider.repeat {
+                checkpoints.push((
+                    CheckpointKind::Repeat,
+                    start + (span + 1) as f64 * slider.span_duration,
+                ));
+            }
+        }
+        let end_time = (start + duration / 2.0).max(object.end_time() - END_LENIENCY);
+        checkpoints.push((CheckpointKind::End, end_time));
+        checkpoints.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let follow_radius = circle_radius * FOLLOW_RADIUS_SCALE;
+        let checkpoints = checkpoints
+            .into_iter()
+            .map(|(kind, time)| {
+                let (x, y) = slider.position_at(time - start);
+                let (stack_x, stack_y) = object.stack_offset();
+                let position = (x + stack_x, y + stack_y);
+                // the last frame at or before the checkpoint is where the cursor was
+                let frame_index = frames
+                    .partition_point(|frame| frame.total_time as f64 <= time)
+                    .checked_sub(1);
+                let hit = frame_index.is_some_and(|frame_index| {
+                    let frame = &frames[frame_index];
+                    let distance = ((frame.x as f64 - position.0).powi(2)
+                        + (frame.y as f64 - position.1).powi(2))
+                    .sqrt();
+                    frame.is_holding() && distance <= follow_radius
+                });
+
+                Checkpoint {
+                    kind,
+                    time: time / 1000.0,
+                    position,
+                    hit,
+                    frame_index,
+                }
+            })
+            .collect();
+
+        SliderJudgement {
+            head_hit,
+            checkpoints,
+        }
+    }
+
+    /// Ticks and repeats the cursor wasn't following, each of which breaks combo.
+    pub fn missed_ticks(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.checkpoints
+            .iter()
+            .filter(|checkpoint| !checkpoint.hit && checkpoint.kind != CheckpointKind::End)
+    }
+
+    /// Dropping the head, a tick or a repeat breaks combo; dropping the end only costs accuracy.
+    pub fn is_break(&self) -> bool {
+        !self.head_hit || self.missed_ticks().next().is_some()
+    }
+
+    /// The slider is judged on the share of the head and checkpoints that were hit.
+    pub fn result(&self) -> HitResult {
+        let total = self.checkpoints.len() + 1;
+        let hit = self
+            .checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.hit)
+            .count()
+            + self.head_hit as usize;
+
+        if hit == total {
+            HitResult::Great
+        } else if hit * 2 >= total {
+            HitResult::Ok
+        } else if hit > 0 {
+            HitResult::Meh
+        } else {
+            HitResult::Miss
+        }
+    }
+}
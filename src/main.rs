@@ -1,7 +1,10 @@
 mod app;
+mod audio;
 mod graphics;
 mod gui;
+mod judgement;
 mod models;
+mod skin;
 
 fn main() {
     env_logger::init();
@@ -9,6 +12,3 @@ fn main() {
     let mut app = app::App::default();
     app.run();
 }
-
-
-
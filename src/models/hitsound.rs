@@ -0,0 +1,172 @@
+use super::osu_map::{HitObject, HitType, OsuMap, SampleSet};
+
+/// Additions played on top of the normal hit sound, as stored in a hit object.
+/// The normal sound always plays, so its bit is ignored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HitSounds(u8);
+
+impl HitSounds {
+    pub const WHISTLE: HitSounds = HitSounds(1 << 1);
+    pub const FINISH: HitSounds = HitSounds(1 << 2);
+    pub const CLAP: HitSounds = HitSounds(1 << 3);
+
+    /// Additions with the name of their sample.
+    const ADDITIONS: [(HitSounds, &'static str); 3] = [
+        (HitSounds::WHISTLE, "hitwhistle"),
+        (HitSounds::FINISH, "hitfinish"),
+        (HitSounds::CLAP, "hitclap"),
+    ];
+
+    pub fn from_bits(bits: u8) -> Self {
+        HitSounds(bits)
+    }
+
+    pub fn contains(self, other: HitSounds) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Sample sets of a hit object or slider edge, none meaning the timing point's.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SampleSets {
+    pub(crate) normal: Option<SampleSet>,
+    /// Set of whistles, finishes and claps, the normal set if none.
+    pub(crate) addition: Option<SampleSet>,
+}
+
+/// The `hitSample` field of a hit object.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HitSample {
+    pub(crate) sets: SampleSets,
+    /// Custom sample index, 0 for the timing point's.
+    pub(crate) index: u32,
+    /// Volume in percent, 0 for the timing point's.
+    pub(crate) volume: u32,
+    /// File in the beatmap folder played instead of the normal sample and additions.
+    pub(crate) filename: Option<String>,
+}
+
+/// A sample file, without its folder and extension, like `soft-hitwhistle2`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sample {
+    pub(crate) set: SampleSet,
+    pub(crate) name: &'static str,
+    pub(crate) index: u32,
+}
+
+impl Sample {
+    /// Name of the sample in the beatmap folder. Index 1 has no suffix.
+    pub fn beatmap_file_stem(&self) -> String {
+        if self.index > 1 {
+            format!("{}-{}{}", self.set.name(), self.name, self.index)
+        } else {
+            format!("{}-{}", self.set.name(), self.name)
+        }
+    }
+
+    /// Name of the sample in the default bank, which has no custom indices.
+    pub fn default_file_stem(&self) -> String {
+        format!("{}-{}", self.set.name(), self.name)
+    }
+
+    /// Index 0 always uses the default samples, the others look in the beatmap folder first.
+    pub fn is_custom(&self) -> bool {
+        self.index > 0
+    }
+}
+
+/// Everything that plays when an object or a slider edge is hit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitSound {
+    pub(crate) samples: Vec<Sample>,
+    /// Replaces `samples` when set.
+    pub(crate) filename: Option<String>,
+    /// Percent.
+    pub(crate) volume: u32,
+}
+
+impl HitSound {
+    /// A plain hit normal of `set` at full volume, like a metronome tick.
+    pub fn click(set: SampleSet) -> Self {
+        HitSound {
+            samples: vec![Sample {
+                set,
+                name: "hitnormal",
+                index: 0,
+            }],
+            filename: None,
+            volume: 100,
+        }
+    }
+}
+
+impl OsuMap {
+    /// The hit sound of `hit_object`, or of one of its slider edges where 0 is the
+    /// head and the last is the end. Edges without their own sounds use the object's.
+    pub fn hit_sound(&self, hit_object: &HitObject, edge: usize) -> HitSound {
+        let (sounds, sets, time) = match &hit_object.hit_type {
+            HitType::Slider(slider) => (
+                slider
+                    .edge_sounds
+                    .get(edge)
+                    .copied()
+                    .unwrap_or(hit_object.hit_sounds),
+                slider.edge_sets.get(edge).copied().unwrap_or_default(),
+                hit_object.time as f64 + edge as f64 * slider.span_duration,
+            ),
+            // spinners sound when they end
+            HitType::Spinner(_) => (
+                hit_object.hit_sounds,
+                hit_object.hit_sample.sets,
+                hit_object.end_time(),
+            ),
+            HitType::Circle => (
+                hit_object.hit_sounds,
+                hit_object.hit_sample.sets,
+                hit_object.time as f64,
+            ),
+        };
+        let timing = self.timing_at(time);
+        let hit_sample = &hit_object.hit_sample;
+
+        let normal_set = sets
+            .normal
+            .or(hit_object.hit_sample.sets.normal)
+            .unwrap_or(timing.sample_set);
+        let addition_set = sets
+            .addition
+            .or(hit_object.hit_sample.sets.addition)
+            .unwrap_or(normal_set);
+        let index = if hit_sample.index > 0 {
+            hit_sample.index
+        } else {
+            timing.sample_index
+        };
+
+        // the normal sample always plays, even when additions are set
+        let mut samples = vec![Sample {
+            set: normal_set,
+            name: "hitnormal",
+            index,
+        }];
+        for (addition, name) in HitSounds::ADDITIONS {
+            if sounds.contains(addition) {
+                samples.push(Sample {
+                    set: addition_set,
+                    name,
+                    index,
+                });
+            }
+        }
+
+        HitSound {
+            samples,
+            filename: hit_sample.filename.clone(),
+            volume: if hit_sample.volume > 0 {
+                hit_sample.volume
+            } else {
+                timing.volume
+            },
+        }
+    }
+}
@@ -1,2 +1,7 @@
-pub mod osu_replay;
+pub mod hitsound;
+pub mod mods;
 pub mod osu_map;
+pub mod osu_replay;
+pub mod slider_path;
+pub mod spin;
+pub mod stacking;
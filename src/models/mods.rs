@@ -0,0 +1,271 @@
+use std::fmt::{self, Display, Formatter};
+use std::ops::BitOr;
+
+use super::osu_map::{
+    ApproachRate, Difficulty, HitType, OsuMap, OverallDifficulty, PLAYFIELD_HEIGHT,
+};
+
+/// osu!stable mods as stored in a replay.
+///
+/// The raw bits are kept as they were read so they can be written back, while
+/// `contains` treats Nightcore as DoubleTime and Perfect as SuddenDeath.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Mods(u32);
+
+impl Mods {
+    pub const NONE: Mods = Mods(0);
+    pub const NO_FAIL: Mods = Mods(1 << 0);
+    pub const EASY: Mods = Mods(1 << 1);
+    pub const TOUCH_DEVICE: Mods = Mods(1 << 2);
+    pub const HIDDEN: Mods = Mods(1 << 3);
+    pub const HARD_ROCK: Mods = Mods(1 << 4);
+    pub const SUDDEN_DEATH: Mods = Mods(1 << 5);
+    pub const DOUBLE_TIME: Mods = Mods(1 << 6);
+    pub const RELAX: Mods = Mods(1 << 7);
+    pub const HALF_TIME: Mods = Mods(1 << 8);
+    pub const NIGHTCORE: Mods = Mods(1 << 9);
+    pub const FLASHLIGHT: Mods = Mods(1 << 10);
+    pub const AUTOPLAY: Mods = Mods(1 << 11);
+    pub const SPUN_OUT: Mods = Mods(1 << 12);
+    pub const AUTOPILOT: Mods = Mods(1 << 13);
+    pub const PERFECT: Mods = Mods(1 << 14);
+    pub const KEY_4: Mods = Mods(1 << 15);
+    pub const KEY_5: Mods = Mods(1 << 16);
+    pub const KEY_6: Mods = Mods(1 << 17);
+    pub const KEY_7: Mods = Mods(1 << 18);
+    pub const KEY_8: Mods = Mods(1 << 19);
+    pub const FADE_IN: Mods = Mods(1 << 20);
+    pub const RANDOM: Mods = Mods(1 << 21);
+    pub const CINEMA: Mods = Mods(1 << 22);
+    pub const TARGET: Mods = Mods(1 << 23);
+    pub const KEY_9: Mods = Mods(1 << 24);
+    pub const KEY_COOP: Mods = Mods(1 << 25);
+    pub const KEY_1: Mods = Mods(1 << 26);
+    pub const KEY_3: Mods = Mods(1 << 27);
+    pub const KEY_2: Mods = Mods(1 << 28);
+    pub const SCORE_V2: Mods = Mods(1 << 29);
+    pub const MIRROR: Mods = Mods(1 << 30);
+
+    /// Acronyms in the order osu! lists mods. A mod implied by another one comes
+    /// after it so only the implying one is shown.
+    const ACRONYMS: [(Mods, &'static str); 31] = [
+        (Mods::NO_FAIL, "NF"),
+        (Mods::EASY, "EZ"),
+        (Mods::TOUCH_DEVICE, "TD"),
+        (Mods::HIDDEN, "HD"),
+        (Mods::NIGHTCORE, "NC"),
+        (Mods::DOUBLE_TIME, "DT"),
+        (Mods::HALF_TIME, "HT"),
+        (Mods::HARD_ROCK, "HR"),
+        (Mods::PERFECT, "PF"),
+        (Mods::SUDDEN_DEATH, "SD"),
+        (Mods::RELAX, "RX"),
+        (Mods::FLASHLIGHT, "FL"),
+        (Mods::AUTOPLAY, "AT"),
+        (Mods::SPUN_OUT, "SO"),
+        (Mods::AUTOPILOT, "AP"),
+        (Mods::KEY_1, "1K"),
+        (Mods::KEY_2, "2K"),
+        (Mods::KEY_3, "3K"),
+        (Mods::KEY_4, "4K"),
+        (Mods::KEY_5, "5K"),
+        (Mods::KEY_6, "6K"),
+        (Mods::KEY_7, "7K"),
+        (Mods::KEY_8, "8K"),
+        (Mods::KEY_9, "9K"),
+        (Mods::KEY_COOP, "CO"),
+        (Mods::FADE_IN, "FI"),
+        (Mods::RANDOM, "RD"),
+        (Mods::CINEMA, "CN"),
+        (Mods::TARGET, "TP"),
+        (Mods::SCORE_V2, "V2"),
+        (Mods::MIRROR, "MR"),
+    ];
+
+    pub fn from_bits(bits: u32) -> Self {
+        Mods(bits)
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Whether every mod of `other` is enabled, counting the ones implied by others.
+    pub fn contains(self, other: Mods) -> bool {
+        self.with_implied().0 & other.0 == other.0
+    }
+
+    fn with_implied(self) -> Self {
+        let mut mods = self;
+        if self.0 & Mods::NIGHTCORE.0 != 0 {
+            mods = mods | Mods::DOUBLE_TIME;
+        }
+        if self.0 & Mods::PERFECT.0 != 0 {
+            mods = mods | Mods::SUDDEN_DEATH;
+        }
+        mods
+    }
+
+    /// Acronyms of the enabled mods, leaving out the ones implied by another.
+    pub fn acronyms(self) -> Vec<&'static str> {
+        let mut shown = Mods::NONE;
+        let mut acronyms = Vec::new();
+        for (mods, acronym) in Mods::ACRONYMS {
+            if self.contains(mods) && !shown.contains(mods) {
+                acronyms.push(acronym);
+                shown = shown | mods;
+            }
+        }
+        acronyms
+    }
+
+    /// How fast the song plays, 1.5 with DT and NC and 0.75 with HT.
+    pub fn clock_rate(self) -> f64 {
+        if self.contains(Mods::DOUBLE_TIME) {
+            1.5
+        } else if self.contains(Mods::HALF_TIME) {
+            0.75
+        } else {
+            1.0
+        }
+    }
+
+    /// HR plays the map upside down.
+    pub fn flip_y(self, y: f64) -> f64 {
+        if self.contains(Mods::HARD_ROCK) {
+            PLAYFIELD_HEIGHT - y
+        } else {
+            y
+        }
+    }
+}
+
+impl BitOr for Mods {
+    type Output = Mods;
+
+    fn bitor(self, other: Mods) -> Mods {
+        Mods(self.0 | other.0)
+    }
+}
+
+impl Display for Mods {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let acronyms = self.acronyms();
+        if acronyms.is_empty() {
+            write!(f, "NM")
+        } else {
+            write!(f, "{}", acronyms.concat())
+        }
+    }
+}
+
+impl OsuMap {
+    /// Adjusts the map to how it plays with `mods`. Only meant to be called once,
+    /// on a map as it was parsed.
+    pub fn apply_mods(&mut self, mods: Mods) {
+        self.difficulty = self.difficulty.with_mods(mods);
+        self.mods = mods;
+
+        if mods.contains(Mods::HARD_ROCK) {
+            for hit_object in &mut self.hit_objects {
+                hit_object.y = mods.flip_y(hit_object.y as f64) as i32;
+                if let HitType::Slider(slider) = &mut hit_object.hit_type {
+                    // mirroring keeps the path's lengths, so it doesn't need to be rebuilt
+                    for (_, y) in slider
+                        .curve_points
+                        .iter_mut()
+                        .chain(slider.path.points.iter_mut())
+                    {
+                        *y = mods.flip_y(*y);
+                    }
+                }
+            }
+        }
+
+        // CS and AR changed and HR stacks the flipped positions
+        self.apply_stacking();
+    }
+}
+
+impl Difficulty {
+    /// Difficulty with HR's and EZ's scaling applied.
+    ///
+    /// Times stay in map time, where DT and HT don't change anything; see
+    /// `OverallDifficulty::at_clock_rate` and `ApproachRate::at_clock_rate` for
+    /// what the player felt.
+    pub fn with_mods(&self, mods: Mods) -> Difficulty {
+        let scale = |value: f64, hard_rock_factor: f64| {
+            if mods.contains(Mods::HARD_ROCK) {
+                (value * hard_rock_factor).min(10.0)
+            } else if mods.contains(Mods::EASY) {
+                value * 0.5
+            } else {
+                value
+            }
+        };
+
+        Difficulty {
+            hit_point_drain_rate: scale(self.hit_point_drain_rate, 1.4),
+            circle_size: scale(self.circle_size, 1.3),
+            overall_difficulty: OverallDifficulty::from_value(scale(
+                self.overall_difficulty.value,
+                1.4,
+            )),
+            approach_rate: ApproachRate::from_value(scale(self.approach_rate.value, 1.4)),
+            slider_multiplier: self.slider_multiplier,
+            slider_tick_rate: self.slider_tick_rate,
+        }
+    }
+}
+
+impl OverallDifficulty {
+    /// Hit windows in real time at `clock_rate`, with the OD they would have at 1x.
+    pub fn at_clock_rate(&self, clock_rate: f64) -> OverallDifficulty {
+        let hit_window_300 = self.hit_window_300 / clock_rate;
+        OverallDifficulty {
+            value: (80.0 - hit_window_300 * 1000.0) / 6.0,
+            hit_window_300,
+            hit_window_100: self.hit_window_100 / clock_rate,
+            hit_window_50: self.hit_window_50 / clock_rate,
+        }
+    }
+}
+
+impl ApproachRate {
+    /// Approach and fade-in in real time at `clock_rate`, with the AR they would have at 1x.
+    pub fn at_clock_rate(&self, clock_rate: f64) -> ApproachRate {
+        let preempt = self.preempt / clock_rate;
+        let preempt_ms = preempt * 1000.0;
+        ApproachRate {
+            value: if preempt_ms > 1200.0 {
+                5.0 - (preempt_ms - 1200.0) / 120.0
+            } else {
+                5.0 + (1200.0 - preempt_ms) / 150.0
+            },
+            preempt,
+            fade_in: self.fade_in / clock_rate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_acronyms_in_order() {
+        assert_eq!(
+            (Mods::HIDDEN | Mods::DOUBLE_TIME | Mods::HARD_ROCK).to_string(),
+            "HDDTHR"
+        );
+        assert_eq!(
+            (Mods::HARD_ROCK | Mods::NIGHTCORE | Mods::DOUBLE_TIME | Mods::HIDDEN).to_string(),
+            "HDNCHR"
+        );
+        assert_eq!(
+            (Mods::PERFECT | Mods::SUDDEN_DEATH | Mods::HALF_TIME).to_string(),
+            "HTPF"
+        );
+        assert_eq!(Mods::NONE.to_string(), "NM");
+    }
+}
@@ -3,23 +3,272 @@ use core::fmt;
 use std::{
     fmt::{Display, Formatter},
     fs::File,
-    io::Read,
+    io::{self, Read},
     path::Path,
+    str::FromStr,
 };
 
+use super::{
+    hitsound::{HitSample, HitSounds, SampleSets},
+    mods::Mods,
+    osu_replay::Gamemode,
+    slider_path::SliderPath,
+};
+
+/// Height of the playfield in osu! pixels, which positions of objects and the cursor are in.
+pub const PLAYFIELD_HEIGHT: f64 = 384.0;
+/// Middle of the 512 by 384 playfield, where spinners always are.
+pub const PLAYFIELD_CENTER: (f64, f64) = (256.0, PLAYFIELD_HEIGHT / 2.0);
+
 #[derive(Debug)]
 pub struct OsuMap {
+    pub(crate) format_version: u32,
+    pub(crate) general: General,
+    pub(crate) editor: Editor,
+    pub(crate) metadata: Metadata,
     pub(crate) difficulty: Difficulty,
+    pub(crate) events: Events,
+    pub(crate) timing_points: Vec<TimingPoint>,
+    pub(crate) colours: Colours,
     pub(crate) hit_objects: Vec<HitObject>,
+    /// Mods the map was adjusted for with `apply_mods`.
+    pub(crate) mods: Mods,
+}
+
+#[derive(Debug)]
+pub struct General {
+    pub(crate) audio_filename: String,
+    pub(crate) audio_lead_in: i64,
+    pub(crate) preview_time: i64,
+    pub(crate) countdown: u32,
+    pub(crate) sample_set: SampleSet,
+    pub(crate) stack_leniency: f64,
+    pub(crate) mode: Gamemode,
+    pub(crate) letterbox_in_breaks: bool,
+    pub(crate) widescreen_storyboard: bool,
+    pub(crate) epilepsy_warning: bool,
+}
+
+impl Default for General {
+    fn default() -> Self {
+        General {
+            audio_filename: String::new(),
+            audio_lead_in: 0,
+            preview_time: -1,
+            countdown: 1,
+            sample_set: SampleSet::Normal,
+            stack_leniency: 0.7,
+            mode: Gamemode::Standard,
+            letterbox_in_breaks: false,
+            widescreen_storyboard: false,
+            epilepsy_warning: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SampleSet {
+    Normal,
+    Soft,
+    Drum,
+}
+
+impl SampleSet {
+    /// Sample sets in [TimingPoints] and hit samples are indices, 0 means "use the default".
+    ///
+    /// Unknown indices also use the default, like in osu!.
+    fn from_index(index: u32) -> Option<SampleSet> {
+        match index {
+            1 => Some(SampleSet::Normal),
+            2 => Some(SampleSet::Soft),
+            3 => Some(SampleSet::Drum),
+            _ => None,
+        }
+    }
+
+    /// Prefix of the set's sample files.
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleSet::Normal => "normal",
+            SampleSet::Soft => "soft",
+            SampleSet::Drum => "drum",
+        }
+    }
+}
+
+impl FromStr for SampleSet {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "Normal" | "None" => Ok(SampleSet::Normal),
+            "Soft" => Ok(SampleSet::Soft),
+            "Drum" => Ok(SampleSet::Drum),
+            _ => Err(format!("unknown sample set {:?}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Editor {
+    pub(crate) bookmarks: Vec<u64>,
+    pub(crate) distance_spacing: f64,
+    pub(crate) beat_divisor: u32,
+    pub(crate) grid_size: u32,
+    pub(crate) timeline_zoom: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct Metadata {
+    pub(crate) title: String,
+    pub(crate) title_unicode: String,
+    pub(crate) artist: String,
+    pub(crate) artist_unicode: String,
+    pub(crate) creator: String,
+    pub(crate) version: String,
+    pub(crate) source: String,
+    pub(crate) tags: Vec<String>,
+    pub(crate) beatmap_id: Option<i32>,
+    pub(crate) beatmap_set_id: Option<i32>,
+}
+
+impl Display for Metadata {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} [{}] ({})",
+            self.artist, self.title, self.version, self.creator
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Events {
+    pub(crate) background: Option<Background>,
+    pub(crate) video: Option<Video>,
+    pub(crate) breaks: Vec<Break>,
+}
+
+#[derive(Debug)]
+pub struct Background {
+    pub(crate) filename: String,
+    pub(crate) x_offset: i32,
+    pub(crate) y_offset: i32,
+}
+
+#[derive(Debug)]
+pub struct Video {
+    pub(crate) start_time: i64,
+    pub(crate) filename: String,
+    pub(crate) x_offset: i32,
+    pub(crate) y_offset: i32,
+}
+
+#[derive(Debug)]
+pub struct Break {
+    pub(crate) start_time: u64,
+    pub(crate) end_time: u64,
+}
+
+#[derive(Debug)]
+pub struct TimingPoint {
+    pub(crate) time: f64,
+    pub(crate) beat_length: f64,
+    pub(crate) meter: u32,
+    pub(crate) sample_set: Option<SampleSet>,
+    pub(crate) sample_index: u32,
+    pub(crate) volume: u32,
+    pub(crate) uninherited: bool,
+    pub(crate) effects: u32,
+}
+
+/// The timing state in effect at some point of the beatmap, see [OsuMap::timing_at].
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    pub(crate) beat_length: f64,
+    pub(crate) slider_velocity: f64,
+    pub(crate) meter: u32,
+    pub(crate) sample_set: SampleSet,
+    pub(crate) sample_index: u32,
+    pub(crate) volume: u32,
+    pub(crate) kiai: bool,
+}
+
+impl Timing {
+    pub fn bpm(&self) -> f64 {
+        60000.0 / self.beat_length
+    }
+}
+
+impl TimingPoint {
+    const KIAI: u32 = 1;
+
+    /// Slider velocity multiplier of an inherited point, which stores it as a negative
+    /// inverse percentage.
+    fn slider_velocity(&self) -> f64 {
+        if self.uninherited || self.beat_length.is_nan() {
+            1.0
+        } else {
+            (-100.0 / self.beat_length).clamp(0.1, 10.0)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Colours {
+    pub(crate) combo_colours: Vec<(u8, u8, u8)>,
+    pub(crate) slider_track_override: Option<(u8, u8, u8)>,
+    pub(crate) slider_border: Option<(u8, u8, u8)>,
 }
 
 #[derive(Debug)]
 pub struct HitObject {
-    pub(crate) x: u32,
-    pub(crate) y: u32,
+    /// Position as written in the map, before stacking.
+    pub(crate) x: i32,
+    pub(crate) y: i32,
     pub(crate) time: u64,
     pub(crate) hit_type: HitType,
+    /// Whether the object starts a combo, after spinners and the first object are accounted for.
     pub(crate) new_combo: bool,
+    pub(crate) hit_sounds: HitSounds,
+    pub(crate) hit_sample: HitSample,
+    /// Combo colours to skip when starting a new combo.
+    pub(crate) combo_skip: u32,
+    /// Number shown on the object, counting from 1 in each combo.
+    pub(crate) combo_number: u32,
+    /// Index of the combo colour, wrapping around the map's or skin's colours.
+    pub(crate) combo_colour_index: usize,
+    /// How many objects this one is stacked on, negative when stacked under a slider end.
+    pub(crate) stack_height: i32,
+    /// Position after stacking, filled in by `OsuMap::apply_stacking`.
+    pub(crate) stacked_position: (f64, f64),
+}
+
+impl HitObject {
+    /// Position the object is drawn and hit at.
+    pub fn position(&self) -> (f64, f64) {
+        self.stacked_position
+    }
+
+    /// How far stacking moved the object. Sliders move as a whole, so this applies
+    /// to every point of their path.
+    pub fn stack_offset(&self) -> (f64, f64) {
+        (
+            self.stacked_position.0 - self.x as f64,
+            self.stacked_position.1 - self.y as f64,
+        )
+    }
+
+    /// Time in milliseconds at which the object is over.
+    pub fn end_time(&self) -> f64 {
+        match &self.hit_type {
+            HitType::Circle => self.time as f64,
+            HitType::Slider(slider) => {
+                self.time as f64 + slider.span_duration * slider.repeat as f64
+            }
+            HitType::Spinner(spinner) => spinner.end_time as f64,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -45,8 +294,77 @@ pub struct Slider {
     pub(crate) curve_points: Vec<(f64, f64)>,
     pub(crate) repeat: u32,
     pub(crate) pixel_length: f64,
-    pub(crate) edge_sounds: Option<Vec<u32>>,
-    pub(crate) edge_sets: Option<Vec<String>>,
+    /// Sounds of the head, each repeat and the end, empty to use the object's.
+    pub(crate) edge_sounds: Vec<HitSounds>,
+    pub(crate) edge_sets: Vec<SampleSets>,
+    /// Time in milliseconds to travel the path once, filled in from the timing points.
+    pub(crate) span_duration: f64,
+    /// osu! pixels per millisecond.
+    pub(crate) velocity: f64,
+    /// osu! pixels between two slider ticks.
+    pub(crate) tick_distance: f64,
+    pub(crate) path: SliderPath,
+}
+
+impl Slider {
+    /// Progress along the path `elapsed` milliseconds after the slider started,
+    /// going back and forth on repeats. 0 is the head, 1 the end of the path.
+    pub fn progress_at(&self, elapsed: f64) -> f64 {
+        if self.span_duration <= 0.0 || self.repeat == 0 {
+            return 0.0;
+        }
+
+        let spans = (elapsed / self.span_duration).clamp(0.0, self.repeat as f64);
+        let span = (spans.floor() as u32).min(self.repeat - 1);
+        let progress = spans - span as f64;
+        if span % 2 == 1 {
+            1.0 - progress
+        } else {
+            progress
+        }
+    }
+
+    /// Position of the slider ball `elapsed` milliseconds after the slider started.
+    pub fn position_at(&self, elapsed: f64) -> (f64, f64) {
+        self.path.position_at(self.progress_at(elapsed))
+    }
+
+    /// Distances along the path of the ticks in a span, from the head. Ticks too close
+    /// to the end of the span are dropped.
+    pub fn tick_distances(&self) -> Vec<f64> {
+        let length = self.path.length();
+        let tick_distance = self.tick_distance.clamp(0.0, length);
+        if tick_distance <= 0.0 {
+            return Vec::new();
+        }
+
+        let min_distance_from_end = self.velocity * 10.0;
+        (1..)
+            .map(|index| index as f64 * tick_distance)
+            .take_while(|distance| *distance < length - min_distance_from_end)
+            .collect()
+    }
+
+    /// Ticks of span `span` as `(time since the slider start, distance along the path)`,
+    /// in the order the slider ball passes them.
+    pub fn span_ticks(&self, span: u32) -> Vec<(f64, f64)> {
+        let length = self.path.length();
+        let span_start = span as f64 * self.span_duration;
+        let mut ticks: Vec<(f64, f64)> = self
+            .tick_distances()
+            .into_iter()
+            .map(|distance| {
+                let travelled = if span % 2 == 1 {
+                    length - distance
+                } else {
+                    distance
+                };
+                (span_start + travelled / self.velocity, distance)
+            })
+            .collect();
+        ticks.sort_by(|a, b| a.0.total_cmp(&b.0));
+        ticks
+    }
 }
 
 #[derive(Debug)]
@@ -67,6 +385,7 @@ enum HitTypeBits {
     Slider = 2,
     NewCombo = 4,
     Spinner = 8,
+    ComboSkip = 0b0111_0000,
 }
 
 #[derive(Debug, Default)]
@@ -84,6 +403,43 @@ pub struct ApproachRate {
     pub(crate) fade_in: f64,
 }
 
+#[derive(Debug)]
+pub enum OsuMapParseError {
+    Io(io::Error),
+    Syntax {
+        section: String,
+        line: usize,
+        field: &'static str,
+        reason: String,
+    },
+}
+
+impl Display for OsuMapParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            OsuMapParseError::Io(e) => write!(f, "Failed to read beatmap: {}", e),
+            OsuMapParseError::Syntax {
+                section,
+                line,
+                field,
+                reason,
+            } => write!(
+                f,
+                "[{}] line {}: invalid {}: {}",
+                section, line, field, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OsuMapParseError {}
+
+impl From<io::Error> for OsuMapParseError {
+    fn from(e: io::Error) -> Self {
+        OsuMapParseError::Io(e)
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct Difficulty {
     pub(crate) hit_point_drain_rate: f64,
@@ -94,6 +450,13 @@ pub struct Difficulty {
     pub(crate) slider_tick_rate: f64,
 }
 
+impl Difficulty {
+    /// Hit circle radius in osu! pixels.
+    pub fn circle_radius(&self) -> f64 {
+        54.4 - 4.48 * self.circle_size
+    }
+}
+
 impl TryFrom<u8> for HitTypeBits {
     type Error = ();
 
@@ -110,155 +473,852 @@ impl TryFrom<u8> for HitTypeBits {
     }
 }
 
+impl OverallDifficulty {
+    pub(crate) fn from_value(value: f64) -> Self {
+        OverallDifficulty {
+            value,
+            hit_window_300: (80.0 - 6.0 * value) / 1000.0,
+            hit_window_100: (140.0 - 8.0 * value) / 1000.0,
+            hit_window_50: (200.0 - 10.0 * value) / 1000.0,
+        }
+    }
+}
+
+impl ApproachRate {
+    pub(crate) fn from_value(value: f64) -> Self {
+        let (preempt, fade_in) = if value < 5.0 {
+            (
+                (1200.0 + 600.0 * (5.0 - value) / 5.0) / 1000.0,
+                (800.0 + 400.0 * (5.0 - value) / 5.0) / 1000.0,
+            )
+        } else if value == 5.0 {
+            (1.2, 0.8)
+        } else {
+            (
+                (1200.0 - 750.0 * (value - 5.0) / 5.0) / 1000.0,
+                (800.0 - 500.0 * (value - 5.0) / 5.0) / 1000.0,
+            )
+        };
+        ApproachRate {
+            value,
+            preempt,
+            fade_in,
+        }
+    }
+}
+
+/// Position of the line currently being parsed, used to build errors.
+struct Line<'a> {
+    section: &'a str,
+    number: usize,
+}
+
+impl Line<'_> {
+    fn error(&self, field: &'static str, reason: impl Display) -> OsuMapParseError {
+        OsuMapParseError::Syntax {
+            section: self.section.to_string(),
+            line: self.number,
+            field,
+            reason: reason.to_string(),
+        }
+    }
+
+    fn parse<T>(&self, value: Option<&str>, field: &'static str) -> Result<T, OsuMapParseError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = value.ok_or_else(|| self.error(field, "missing value"))?;
+        value.trim().parse().map_err(|e| self.error(field, e))
+    }
+
+    /// Like [Line::parse], but also rejects NaN and infinities, which break slider path math.
+    fn parse_finite(
+        &self,
+        value: Option<&str>,
+        field: &'static str,
+    ) -> Result<f64, OsuMapParseError> {
+        let number: f64 = self.parse(value, field)?;
+        if number.is_finite() {
+            Ok(number)
+        } else {
+            Err(self.error(field, format!("{} is not a finite number", number)))
+        }
+    }
+}
+
 impl OsuMap {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Self {
-        let mut file = File::open(path).unwrap();
+    /// Returns the BPM, slider velocity and sample settings active at `time` (milliseconds).
+    ///
+    /// Before the first timing point the first one applies, like in osu!.
+    pub fn timing_at(&self, time: f64) -> Timing {
+        let first_point = self.timing_points.first();
+        let uninherited = self
+            .timing_points
+            .iter()
+            .filter(|point| point.uninherited)
+            .take_while(|point| point.time <= time)
+            .last()
+            .or_else(|| self.timing_points.iter().find(|point| point.uninherited));
+
+        // An inherited point overrides the reset of an uninherited point at the same time
+        let mut velocity_point: Option<&TimingPoint> = None;
+        for point in self
+            .timing_points
+            .iter()
+            .take_while(|point| point.time <= time)
+        {
+            match velocity_point {
+                Some(current) if point.time == current.time && point.uninherited => {}
+                _ => velocity_point = Some(point),
+            }
+        }
+
+        let sample_point = self
+            .timing_points
+            .iter()
+            .take_while(|point| point.time <= time)
+            .last()
+            .or(first_point);
+
+        Timing {
+            beat_length: uninherited.map_or(500.0, |point| point.beat_length),
+            slider_velocity: velocity_point.map_or(1.0, TimingPoint::slider_velocity),
+            meter: uninherited.map_or(4, |point| point.meter),
+            sample_set: sample_point
+                .and_then(|point| point.sample_set)
+                .unwrap_or(self.general.sample_set),
+            sample_index: sample_point.map_or(0, |point| point.sample_index),
+            volume: sample_point.map_or(100, |point| point.volume),
+            kiai: sample_point.is_some_and(|point| point.effects & TimingPoint::KIAI != 0),
+        }
+    }
+
+    /// Returns the kiai sections as `(start, end)` in milliseconds. One still going at the
+    /// last timing point ends with the last hit object.
+    pub fn kiai_sections(&self) -> Vec<(f64, f64)> {
+        let mut sections = Vec::new();
+        let mut start = None;
+        for (index, point) in self.timing_points.iter().enumerate() {
+            // Of points at the same time the last one applies, like in `timing_at`
+            let next = self.timing_points.get(index + 1);
+            if next.is_some_and(|next| next.time == point.time) {
+                continue;
+            }
+
+            let kiai = point.effects & TimingPoint::KIAI != 0;
+            match start {
+                None if kiai => start = Some(point.time),
+                Some(section_start) if !kiai => {
+                    sections.push((section_start, point.time));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = start {
+            let end = self.hit_objects.last().map_or(start, HitObject::end_time);
+            sections.push((start, end.max(start)));
+        }
+        sections
+    }
+
+    /// Resolves where combos start like osu! does, then numbers the objects in each
+    /// combo and picks their colours.
+    fn apply_combos(&mut self) {
+        let format_version = self.format_version;
+
+        let mut force_new_combo = false;
+        let mut pending_skip = 0;
+        let mut colour_index: Option<usize> = None;
+        let mut combo_number = 0;
+        for (index, hit_object) in self.hit_objects.iter_mut().enumerate() {
+            if let HitType::Spinner(_) = hit_object.hit_type {
+                // spinners don't start a combo themselves but make the next object start one
+                force_new_combo |= format_version <= 8 || hit_object.new_combo;
+                pending_skip += hit_object.combo_skip;
+                hit_object.new_combo = false;
+            } else {
+                hit_object.new_combo |= force_new_combo;
+                hit_object.combo_skip += pending_skip;
+                force_new_combo = false;
+                pending_skip = 0;
+            }
+            hit_object.new_combo |= index == 0;
+
+            if hit_object.new_combo {
+                let skip = hit_object.combo_skip as usize;
+                colour_index =
+                    Some(colour_index.map_or(skip, |colour_index| colour_index + 1 + skip));
+                combo_number = 0;
+            }
+            combo_number += 1;
+            hit_object.combo_number = combo_number;
+            hit_object.combo_colour_index = colour_index.unwrap_or(0);
+        }
+    }
+
+    fn apply_slider_timing(&mut self) {
+        for index in 0..self.hit_objects.len() {
+            let timing = self.timing_at(self.hit_objects[index].time as f64);
+            let Difficulty {
+                slider_multiplier,
+                slider_tick_rate,
+                ..
+            } = self.difficulty;
+            let format_version = self.format_version;
+
+            if let HitType::Slider(slider) = &mut self.hit_objects[index].hit_type {
+                let pixels_per_beat = 100.0 * slider_multiplier * timing.slider_velocity;
+                slider.velocity = pixels_per_beat / timing.beat_length;
+                slider.span_duration = slider.pixel_length / slider.velocity;
+
+                // Before v8 ticks were spaced without taking the slider velocity into account
+                slider.tick_distance = if format_version < 8 {
+                    pixels_per_beat / timing.slider_velocity / slider_tick_rate
+                } else {
+                    pixels_per_beat / slider_tick_rate
+                };
+            }
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, OsuMapParseError> {
+        let file = File::open(path)?;
+        Self::from_reader(file)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, OsuMapParseError> {
         let mut data = String::new();
-        file.read_to_string(&mut data).unwrap();
-
-        let difficulty = data
-            .lines()
-            .skip_while(|line| !line.starts_with("[Difficulty]"))
-            .skip(1)
-            .take_while(|line| !line.is_empty())
-            .fold(Difficulty::default(), |diff, line| {
-                let mut parts = line.split(':');
-                let key = parts.next().unwrap();
-                let value = parts.next().unwrap().trim().parse().unwrap();
-                match key {
-                    "HPDrainRate" => Difficulty {
-                        hit_point_drain_rate: value,
-                        ..diff
-                    },
-                    "CircleSize" => Difficulty {
-                        circle_size: value,
-                        ..diff
-                    },
-                    "OverallDifficulty" => Difficulty {
-                        overall_difficulty: OverallDifficulty {
-                            value,
-                            hit_window_300: (80.0 - 6.0 * value) / 1000.0,
-                            hit_window_100: (140.0 - 8.0 * value) / 1000.0,
-                            hit_window_50: (200.0 - 10.0 * value) / 1000.0,
-                        },
-                        ..diff
-                    },
-                    "ApproachRate" => {
-                        let (preempt, fade_in) = if value < 5.0 {
-                            (
-                                (1200.0 + 600.0 * (5.0 - value) / 5.0) / 1000.0,
-                                (800.0 + 400.0 * (5.0 - value) / 5.0) / 1000.0,
-                            )
-                        } else if value == 5.0 {
-                            (1.2, 0.8)
-                        } else {
-                            (
-                                (1200.0 - 750.0 * (value - 5.0) / 5.0) / 1000.0,
-                                (800.0 - 500.0 * (value - 5.0) / 5.0) / 1000.0,
-                            )
-                        };
-                        Difficulty {
-                            approach_rate: ApproachRate {
-                                value,
-                                preempt,
-                                fade_in,
-                            },
-                            ..diff
+        reader.read_to_string(&mut data)?;
+        data.parse()
+    }
+}
+
+impl FromStr for OsuMap {
+    type Err = OsuMapParseError;
+
+    fn from_str(data: &str) -> Result<Self, Self::Err> {
+        let mut section = "";
+        let mut format_version = 0;
+        let mut general = General::default();
+        let mut editor = Editor::default();
+        let mut metadata = Metadata::default();
+        let mut difficulty = Difficulty::default();
+        let mut approach_rate_set = false;
+        let mut events = Events::default();
+        let mut timing_points = Vec::new();
+        let mut colours = Colours::default();
+        let mut hit_objects = Vec::new();
+
+        for (index, raw_line) in data.lines().enumerate() {
+            let line_text = raw_line.trim();
+            if line_text.is_empty() || line_text.starts_with("//") {
+                continue;
+            }
+
+            if line_text.starts_with('[') && line_text.ends_with(']') {
+                section = &line_text[1..line_text.len() - 1];
+                continue;
+            }
+
+            let line = Line {
+                section,
+                number: index + 1,
+            };
+
+            if section.is_empty() {
+                if let Some(version) = line_text
+                    .trim_start_matches('\u{feff}')
+                    .strip_prefix("osu file format v")
+                {
+                    format_version = line.parse(Some(version), "format version")?;
+                }
+                continue;
+            }
+
+            match section {
+                "General" | "Editor" | "Metadata" | "Difficulty" | "Colours" => {
+                    let (key, value) = line_text
+                        .split_once(':')
+                        .ok_or_else(|| line.error("key", "expected `Key:Value`"))?;
+                    let (key, value) = (key.trim(), value.trim());
+                    match section {
+                        "General" => parse_general(&mut general, key, value, &line)?,
+                        "Editor" => parse_editor(&mut editor, key, value, &line)?,
+                        "Metadata" => parse_metadata(&mut metadata, key, value, &line)?,
+                        "Difficulty" => {
+                            approach_rate_set |= key == "ApproachRate";
+                            difficulty = parse_difficulty(difficulty, key, value, &line)?;
                         }
+                        _ => parse_colour(&mut colours, key, value, &line)?,
                     }
-                    "SliderMultiplier" => Difficulty {
-                        slider_multiplier: value,
-                        ..diff
-                    },
-                    "SliderTickRate" => Difficulty {
-                        slider_tick_rate: value,
-                        ..diff
-                    },
-                    _ => unreachable!(),
                 }
+                // Indented lines belong to storyboard commands
+                "Events" if !raw_line.starts_with([' ', '_']) => {
+                    parse_event(&mut events, line_text, &line)?
+                }
+                "TimingPoints" => timing_points.push(parse_timing_point(line_text, &line)?),
+                "HitObjects" => hit_objects.push(parse_hit_object(line_text, &line)?),
+                _ => {}
+            }
+        }
+
+        // Old beatmaps don't have an ApproachRate, it used to be the same as OverallDifficulty
+        if !approach_rate_set {
+            difficulty.approach_rate =
+                ApproachRate::from_value(difficulty.overall_difficulty.value);
+        }
+
+        timing_points.sort_by(|a: &TimingPoint, b| a.time.total_cmp(&b.time));
+
+        let mut map = OsuMap {
+            format_version,
+            general,
+            editor,
+            metadata,
+            difficulty,
+            events,
+            timing_points,
+            colours,
+            hit_objects,
+            mods: Mods::NONE,
+        };
+        map.apply_slider_timing();
+        map.apply_combos();
+        map.apply_stacking();
+
+        Ok(map)
+    }
+}
+
+fn parse_general(
+    general: &mut General,
+    key: &str,
+    value: &str,
+    line: &Line,
+) -> Result<(), OsuMapParseError> {
+    match key {
+        "AudioFilename" => general.audio_filename = value.to_string(),
+        "AudioLeadIn" => general.audio_lead_in = line.parse(Some(value), "AudioLeadIn")?,
+        "PreviewTime" => general.preview_time = line.parse(Some(value), "PreviewTime")?,
+        "Countdown" => general.countdown = line.parse(Some(value), "Countdown")?,
+        // values osu! doesn't know fall back to the default rather than rejecting the map
+        "SampleSet" => general.sample_set = value.parse().unwrap_or(SampleSet::Normal),
+        "StackLeniency" => general.stack_leniency = line.parse(Some(value), "StackLeniency")?,
+        "Mode" => {
+            general.mode = value
+                .parse::<u8>()
+                .ok()
+                .and_then(|mode| Gamemode::try_from(mode).ok())
+                .unwrap_or(Gamemode::Standard)
+        }
+        "LetterboxInBreaks" => general.letterbox_in_breaks = parse_flag(value).unwrap_or_default(),
+        "WidescreenStoryboard" => {
+            general.widescreen_storyboard = parse_flag(value).unwrap_or_default()
+        }
+        "EpilepsyWarning" => general.epilepsy_warning = parse_flag(value).unwrap_or_default(),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_editor(
+    editor: &mut Editor,
+    key: &str,
+    value: &str,
+    line: &Line,
+) -> Result<(), OsuMapParseError> {
+    match key {
+        "Bookmarks" => {
+            editor.bookmarks = value
+                .split(',')
+                .filter(|bookmark| !bookmark.trim().is_empty())
+                .map(|bookmark| line.parse(Some(bookmark), "Bookmarks"))
+                .collect::<Result<_, _>>()?
+        }
+        "DistanceSpacing" => {
+            editor.distance_spacing = line.parse(Some(value), "DistanceSpacing")?
+        }
+        "BeatDivisor" => editor.beat_divisor = line.parse(Some(value), "BeatDivisor")?,
+        "GridSize" => editor.grid_size = line.parse(Some(value), "GridSize")?,
+        "TimelineZoom" => editor.timeline_zoom = line.parse(Some(value), "TimelineZoom")?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_metadata(
+    metadata: &mut Metadata,
+    key: &str,
+    value: &str,
+    line: &Line,
+) -> Result<(), OsuMapParseError> {
+    match key {
+        "Title" => metadata.title = value.to_string(),
+        "TitleUnicode" => metadata.title_unicode = value.to_string(),
+        "Artist" => metadata.artist = value.to_string(),
+        "ArtistUnicode" => metadata.artist_unicode = value.to_string(),
+        "Creator" => metadata.creator = value.to_string(),
+        "Version" => metadata.version = value.to_string(),
+        "Source" => metadata.source = value.to_string(),
+        "Tags" => metadata.tags = value.split_whitespace().map(str::to_string).collect(),
+        "BeatmapID" => metadata.beatmap_id = Some(line.parse(Some(value), "BeatmapID")?),
+        "BeatmapSetID" => metadata.beatmap_set_id = Some(line.parse(Some(value), "BeatmapSetID")?),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_colour(
+    colours: &mut Colours,
+    key: &str,
+    value: &str,
+    line: &Line,
+) -> Result<(), OsuMapParseError> {
+    let mut parts = value.split(',');
+    let colour = (
+        line.parse(parts.next(), "red")?,
+        line.parse(parts.next(), "green")?,
+        line.parse(parts.next(), "blue")?,
+    );
+    match key {
+        "SliderTrackOverride" => colours.slider_track_override = Some(colour),
+        "SliderBorder" => colours.slider_border = Some(colour),
+        _ if key.starts_with("Combo") => colours.combo_colours.push(colour),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A `0` or `1` flag, `None` for anything else.
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+fn parse_event(events: &mut Events, text: &str, line: &Line) -> Result<(), OsuMapParseError> {
+    let mut parts = text.split(',');
+    let event_type = parts.next().unwrap_or_default().trim();
+    let filename = |part: Option<&str>| part.map(|name| name.trim().trim_matches('"').to_string());
+    match event_type {
+        "0" | "Background" => {
+            let _start_time = parts.next();
+            events.background = Some(Background {
+                filename: filename(parts.next())
+                    .ok_or_else(|| line.error("background filename", "missing value"))?,
+                x_offset: parts
+                    .next()
+                    .map_or(Ok(0), |x| line.parse(Some(x), "x offset"))?,
+                y_offset: parts
+                    .next()
+                    .map_or(Ok(0), |y| line.parse(Some(y), "y offset"))?,
+            });
+        }
+        "1" | "Video" => {
+            events.video = Some(Video {
+                start_time: line.parse(parts.next(), "video start time")?,
+                filename: filename(parts.next())
+                    .ok_or_else(|| line.error("video filename", "missing value"))?,
+                x_offset: parts
+                    .next()
+                    .map_or(Ok(0), |x| line.parse(Some(x), "x offset"))?,
+                y_offset: parts
+                    .next()
+                    .map_or(Ok(0), |y| line.parse(Some(y), "y offset"))?,
             });
+        }
+        "2" | "Break" => events.breaks.push(Break {
+            start_time: line.parse(parts.next(), "break start time")?,
+            end_time: line.parse(parts.next(), "break end time")?,
+        }),
+        // Storyboard sprites, animations and samples
+        _ => {}
+    }
+    Ok(())
+}
 
-        let hit_objects = data
-            .lines()
-            .skip_while(|line| !line.starts_with("[HitObjects]"))
-            .skip(1)
-            .take_while(|line| !line.is_empty())
-            .map(|line| {
-                let mut parts = line.split(',');
-                let x = parts.next().unwrap().parse().unwrap();
-                let y = parts.next().unwrap().parse().unwrap();
-                let time = parts.next().unwrap().parse().unwrap();
-
-                let hit_type =
-                    HitTypeBits::try_from(parts.next().unwrap().parse::<u8>().unwrap()).unwrap();
-
-                // skip hitsound
-                let _ = parts.next().unwrap();
-
-                let hit_type = match hit_type {
-                    HitTypeBits::Circle => HitType::Circle,
-                    HitTypeBits::Slider => {
-                        let mut slider_parts = parts.next().unwrap().split('|');
-                        let curve_type = match slider_parts.next().unwrap() {
-                            "L" => SliderCurveType::Linear,
-                            "P" => SliderCurveType::PerfectCircle,
-                            "B" => SliderCurveType::Bezier,
-                            "C" => SliderCurveType::Catmull,
-                            _ => unreachable!(),
-                        };
-                        let curve_points = {
-                            let mut curve_points = Vec::new();
-                            while let Some(curve_point) = slider_parts.next() {
-                                let mut point_parts = curve_point.split(':');
-                                let x = point_parts.next().unwrap().parse().unwrap();
-                                let y = point_parts.next().unwrap().parse().unwrap();
-                                curve_points.push((x, y));
-                            }
-                            curve_points
-                        };
-
-                        let repeat = parts.next().unwrap().parse().unwrap();
-                        let pixel_length = parts.next().unwrap().parse().unwrap();
-
-                        let edge_sounds = parts.next().map(|next| {
-                            next.split('|')
-                                .map(|sound| sound.parse::<u32>().unwrap())
-                                .collect()
-                        });
-
-                        let edge_sets = parts
-                            .next()
-                            .map(|next| next.split('|').map(|set| set.to_string()).collect());
-
-                        HitType::Slider(Slider {
-                            curve_type,
-                            curve_points,
-                            repeat,
-                            pixel_length,
-                            edge_sounds,
-                            edge_sets,
-                        })
-                    }
-                    HitTypeBits::Spinner => {
-                        let end_time = parts.next().unwrap().parse().unwrap();
-                        HitType::Spinner(Spinner { end_time })
-                    }
-                    _ => unreachable!(),
-                };
+fn parse_timing_point(text: &str, line: &Line) -> Result<TimingPoint, OsuMapParseError> {
+    let mut parts = text.split(',');
+    let time = line.parse(parts.next(), "time")?;
+    let beat_length = line.parse(parts.next(), "beat length")?;
+
+    // Old beatmaps leave out the trailing fields
+    let meter = parts
+        .next()
+        .map_or(Ok(4), |meter| line.parse(Some(meter), "meter"))?;
+    let sample_set = match parts.next() {
+        Some(sample_set) => SampleSet::from_index(line.parse(Some(sample_set), "sample set")?),
+        None => None,
+    };
+    let sample_index = parts
+        .next()
+        .map_or(Ok(0), |index| line.parse(Some(index), "sample index"))?;
+    let volume = parts
+        .next()
+        .map_or(Ok(100), |volume| line.parse(Some(volume), "volume"))?;
+    let uninherited = parts
+        .next()
+        .and_then(|uninherited| parse_flag(uninherited.trim()))
+        .unwrap_or(true);
+    let effects = parts
+        .next()
+        .map_or(Ok(0), |effects| line.parse(Some(effects), "effects"))?;
+
+    Ok(TimingPoint {
+        time,
+        beat_length,
+        meter,
+        sample_set,
+        sample_index,
+        volume,
+        uninherited,
+        effects,
+    })
+}
+
+fn parse_difficulty(
+    diff: Difficulty,
+    key: &str,
+    value: &str,
+    line: &Line,
+) -> Result<Difficulty, OsuMapParseError> {
+    let difficulty = match key {
+        "HPDrainRate" => Difficulty {
+            hit_point_drain_rate: line.parse(Some(value), "HPDrainRate")?,
+            ..diff
+        },
+        "CircleSize" => Difficulty {
+            circle_size: line.parse(Some(value), "CircleSize")?,
+            ..diff
+        },
+        "OverallDifficulty" => Difficulty {
+            overall_difficulty: OverallDifficulty::from_value(
+                line.parse(Some(value), "OverallDifficulty")?,
+            ),
+            ..diff
+        },
+        "ApproachRate" => Difficulty {
+            approach_rate: ApproachRate::from_value(line.parse(Some(value), "ApproachRate")?),
+            ..diff
+        },
+        "SliderMultiplier" => Difficulty {
+            slider_multiplier: line.parse(Some(value), "SliderMultiplier")?,
+            ..diff
+        },
+        "SliderTickRate" => Difficulty {
+            slider_tick_rate: line.parse(Some(value), "SliderTickRate")?,
+            ..diff
+        },
+        // Unknown keys are ignored, the same way osu! does
+        _ => diff,
+    };
+    Ok(difficulty)
+}
+
+fn parse_hit_object(text: &str, line: &Line) -> Result<HitObject, OsuMapParseError> {
+    let mut parts = text.split(',');
+    let x = line.parse(parts.next(), "x")?;
+    let y = line.parse(parts.next(), "y")?;
+    let time = line.parse(parts.next(), "time")?;
+
+    let type_bits = line.parse::<u8>(parts.next(), "type")?;
+    let new_combo = type_bits & HitTypeBits::NewCombo as u8 != 0;
+    let combo_skip = ((type_bits & HitTypeBits::ComboSkip as u8) >> 4) as u32;
+    let hit_type = HitTypeBits::try_from(type_bits)
+        .map_err(|_| line.error("type", format!("unsupported hit object type {}", type_bits)))?;
 
-                HitObject {
-                    x,
-                    y,
-                    time,
-                    hit_type,
-                    new_combo: false,
+    let hit_sounds = HitSounds::from_bits(line.parse(parts.next(), "hitsound")?);
+
+    let hit_type = match hit_type {
+        HitTypeBits::Circle => HitType::Circle,
+        HitTypeBits::Slider => {
+            let mut slider_parts = parts
+                .next()
+                .ok_or_else(|| line.error("curve", "missing value"))?
+                .split('|');
+            let curve_type = match slider_parts.next() {
+                Some("L") => SliderCurveType::Linear,
+                Some("P") => SliderCurveType::PerfectCircle,
+                Some("B") => SliderCurveType::Bezier,
+                Some("C") => SliderCurveType::Catmull,
+                other => {
+                    return Err(line.error(
+                        "curve type",
+                        format!("unknown curve type {:?}", other.unwrap_or_default()),
+                    ))
                 }
+            };
+            let curve_points = slider_parts
+                .map(|curve_point| {
+                    let mut point_parts = curve_point.split(':');
+                    let x = line.parse_finite(point_parts.next(), "curve point x")?;
+                    let y = line.parse_finite(point_parts.next(), "curve point y")?;
+                    Ok((x, y))
+                })
+                .collect::<Result<Vec<_>, OsuMapParseError>>()?;
+
+            let repeat = line.parse(parts.next(), "slides")?;
+            let pixel_length = line.parse_finite(parts.next(), "length")?;
+            let path = SliderPath::new(
+                (x as f64, y as f64),
+                &curve_type,
+                &curve_points,
+                pixel_length,
+            );
+
+            let edge_sounds = match parts.next() {
+                Some(edge_sounds) if !edge_sounds.is_empty() => edge_sounds
+                    .split('|')
+                    .map(|sound| {
+                        Ok(HitSounds::from_bits(
+                            line.parse(Some(sound), "edge sounds")?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, OsuMapParseError>>()?,
+                _ => Vec::new(),
+            };
+
+            let edge_sets = match parts.next() {
+                Some(edge_sets) if !edge_sets.is_empty() => edge_sets
+                    .split('|')
+                    .map(|sets| parse_sample_sets(&mut sets.split(':'), "edge sets", line))
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => Vec::new(),
+            };
+
+            HitType::Slider(Slider {
+                curve_type,
+                curve_points,
+                repeat,
+                pixel_length,
+                edge_sounds,
+                edge_sets,
+                span_duration: 0.0,
+                velocity: 0.0,
+                tick_distance: 0.0,
+                path,
             })
-            .collect();
+        }
+        HitTypeBits::Spinner => {
+            let end_time = line.parse(parts.next(), "end time")?;
+            HitType::Spinner(Spinner { end_time })
+        }
+        // try_from only gives object types, these bits just modify one
+        HitTypeBits::NewCombo | HitTypeBits::ComboSkip => {
+            return Err(line.error("type", format!("unsupported hit object type {}", type_bits)))
+        }
+    };
 
-        OsuMap {
-            difficulty,
-            hit_objects,
+    let hit_sample = parts
+        .next()
+        .map_or(Ok(HitSample::default()), |hit_sample| {
+            parse_hit_sample(hit_sample, line)
+        })?;
+
+    Ok(HitObject {
+        x,
+        y,
+        time,
+        hit_type,
+        new_combo,
+        hit_sounds,
+        hit_sample,
+        combo_skip,
+        combo_number: 0,
+        combo_colour_index: 0,
+        stack_height: 0,
+        stacked_position: (x as f64, y as f64),
+    })
+}
+
+/// Parses `normalSet:additionSet`, where missing sets use the timing point's.
+fn parse_sample_sets<'a>(
+    parts: &mut impl Iterator<Item = &'a str>,
+    field: &'static str,
+    line: &Line,
+) -> Result<SampleSets, OsuMapParseError> {
+    let mut next_set = || match parts.next() {
+        Some(set) if !set.is_empty() => line.parse(Some(set), field).map(SampleSet::from_index),
+        _ => Ok(None),
+    };
+    Ok(SampleSets {
+        normal: next_set()?,
+        addition: next_set()?,
+    })
+}
+
+/// Parses `normalSet:additionSet:index:volume:filename`, any of which can be left out.
+fn parse_hit_sample(text: &str, line: &Line) -> Result<HitSample, OsuMapParseError> {
+    let mut parts = text.split(':');
+    let sets = parse_sample_sets(&mut parts, "hit sample set", line)?;
+    let mut next_number = |field| match parts.next() {
+        Some(number) if !number.is_empty() => line.parse(Some(number), field),
+        _ => Ok(0),
+    };
+    let index = next_number("hit sample index")?;
+    let volume = next_number("hit sample volume")?;
+    let filename = parts
+        .next()
+        .filter(|filename| !filename.is_empty())
+        .map(str::to_string);
+
+    Ok(HitSample {
+        sets,
+        index,
+        volume,
+        filename,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "osu file format v14
+
+[General]
+AudioFilename: audio.mp3
+AudioLeadIn: 500
+Mode: 0
+
+[Metadata]
+Title:Test
+Artist:Someone
+Creator:me
+Version:Hard
+Tags:a b c
+BeatmapID:123
+
+[Difficulty]
+HPDrainRate:5
+CircleSize:4
+OverallDifficulty:8
+ApproachRate:9
+SliderMultiplier:1.4
+SliderTickRate:1
+
+[Events]
+0,0,\"bg.jpg\",0,0
+2,5000,8000
+
+[TimingPoints]
+1000,500,4,2,0,60,1,0
+3000,-50,4,2,0,70,0,1
+
+[Colours]
+Combo1 : 255,0,0
+Combo2 : 0,255,0
+
+[HitObjects]
+100,100,1000,5,0,0:0:0:0:
+200,100,1500,1,2,0:0:0:0:
+100,200,2000,2,0,B|200:200|200:200|300:100,2,150
+256,192,4000,12,0,9000,0:0:0:0:
+";
+
+    fn syntax_error(data: &str) -> (String, usize, &'static str) {
+        match data.parse::<OsuMap>() {
+            Err(OsuMapParseError::Syntax {
+                section,
+                line,
+                field,
+                ..
+            }) => (section, line, field),
+            Err(e) => panic!("expected a syntax error, got {}", e),
+            Ok(_) => panic!("expected a syntax error, got a beatmap"),
         }
     }
+
+    #[test]
+    fn parses_sections() {
+        let map: OsuMap = MAP.parse().unwrap();
+        assert_eq!(map.format_version, 14);
+        assert_eq!(map.general.audio_filename, "audio.mp3");
+        assert_eq!(map.general.audio_lead_in, 500);
+        assert_eq!(map.metadata.title, "Test");
+        assert_eq!(map.metadata.tags, ["a", "b", "c"]);
+        assert_eq!(map.metadata.beatmap_id, Some(123));
+        assert_eq!(map.difficulty.circle_size, 4.0);
+        assert_eq!(map.difficulty.slider_multiplier, 1.4);
+        assert_eq!(map.events.breaks.len(), 1);
+        assert_eq!(map.timing_points.len(), 2);
+        assert_eq!(map.colours.combo_colours, [(255, 0, 0), (0, 255, 0)]);
+        assert_eq!(map.hit_objects.len(), 4);
+        assert!(matches!(map.hit_objects[2].hit_type, HitType::Slider(_)));
+        assert!(matches!(map.hit_objects[3].hit_type, HitType::Spinner(_)));
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        assert_eq!(
+            syntax_error("osu file format v14\n\n[HitObjects]\n1,2,x,1,0"),
+            ("HitObjects".to_string(), 4, "time")
+        );
+        assert_eq!(
+            syntax_error("[Difficulty]\nCircleSize:4\n\nHPDrainRate:high"),
+            ("Difficulty".to_string(), 4, "HPDrainRate")
+        );
+        assert_eq!(
+            syntax_error("[TimingPoints]\n// comment\n1000,fast,4"),
+            ("TimingPoints".to_string(), 3, "beat length")
+        );
+        assert_eq!(
+            syntax_error("[Metadata]\nTitle"),
+            ("Metadata".to_string(), 2, "key")
+        );
+        assert_eq!(
+            syntax_error("[HitObjects]\n256,192,1000,16,0"),
+            ("HitObjects".to_string(), 2, "type")
+        );
+    }
+
+    #[test]
+    fn rejects_non_finite_slider_values() {
+        assert_eq!(
+            syntax_error("[HitObjects]\n100,100,1000,2,0,B|NaN:200|300:100,1,150"),
+            ("HitObjects".to_string(), 2, "curve point x")
+        );
+        assert_eq!(
+            syntax_error("[HitObjects]\n100,100,1000,2,0,L|200:inf,1,150"),
+            ("HitObjects".to_string(), 2, "curve point y")
+        );
+        assert_eq!(
+            syntax_error("[HitObjects]\n100,100,1000,2,0,L|200:200,1,-inf"),
+            ("HitObjects".to_string(), 2, "length")
+        );
+    }
+
+    #[test]
+    fn falls_back_on_unknown_general_values() {
+        let map: OsuMap = "[General]
+SampleSet: Loud
+Mode: 7
+LetterboxInBreaks: yes
+WidescreenStoryboard: 1
+EpilepsyWarning: 2
+
+[TimingPoints]
+1000,500,4,2,0,60,x,0
+2000,-100,4,9,0,60,0,0
+
+[HitObjects]
+256,192,1000,1,0,7:2:0:0:
+"
+        .parse()
+        .unwrap();
+        assert_eq!(map.general.sample_set, SampleSet::Normal);
+        assert_eq!(map.general.mode, Gamemode::Standard);
+        assert!(!map.general.letterbox_in_breaks);
+        assert!(map.general.widescreen_storyboard);
+        assert!(!map.general.epilepsy_warning);
+        assert!(map.timing_points[0].uninherited);
+        assert_eq!(map.timing_points[1].sample_set, None);
+        let sets = map.hit_objects[0].hit_sample.sets;
+        assert_eq!(sets.normal, None);
+        assert_eq!(sets.addition, Some(SampleSet::Soft));
+    }
 }
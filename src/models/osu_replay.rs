@@ -1,14 +1,21 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write as _};
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, BufWriter, Read, Write};
 use std::path::Path;
+use std::string::FromUtf8Error;
+
+use super::mods::Mods;
 
 #[allow(dead_code)]
+#[derive(Debug)]
 pub struct OsuReplay {
     pub(crate) gamemode: Gamemode,
     pub(crate) version: u32,
-    pub(crate) beatmap_hash: String,
-    pub(crate) player_name: String,
-    pub(crate) replay_hash: String,
+    // Strings are `None` when the replay marks them absent, which osu! tells apart from empty
+    pub(crate) beatmap_hash: Option<String>,
+    pub(crate) player_name: Option<String>,
+    pub(crate) replay_hash: Option<String>,
     pub(crate) count_300: u16,
     pub(crate) count_100: u16,
     pub(crate) count_50: u16,
@@ -18,14 +25,21 @@ pub struct OsuReplay {
     pub(crate) score: u32,
     pub(crate) max_combo: u16,
     pub(crate) is_perfect_combo: bool,
-    pub(crate) mods: u32,
-    pub(crate) life_bar_graph: String,
+    pub(crate) mods: Mods,
+    pub(crate) life_bar_graph: Option<String>,
+    /// `life_bar_graph` parsed, in time order.
+    pub(crate) life_bar: Vec<LifeBarPoint>,
     pub(crate) timestamp: u64,
     pub(crate) online_score_id: u64,
     pub(crate) additional_mod_info: Option<f64>,
     pub(crate) replay_data: Vec<ReplayData>,
+    pub(crate) rng_seed: Option<i32>,
+    /// The frames as they were read, written back as they are while the frames are unchanged
+    /// since re-encoding them wouldn't give osu!'s LZMA bytes.
+    pub(crate) replay_data_compressed: Vec<u8>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Gamemode {
     Standard = 0,
     Taiko = 1,
@@ -33,57 +47,127 @@ pub enum Gamemode {
     Mania = 3,
 }
 
+impl TryFrom<u8> for Gamemode {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Gamemode::Standard),
+            1 => Ok(Gamemode::Taiko),
+            2 => Ok(Gamemode::CatchTheBeat),
+            3 => Ok(Gamemode::Mania),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OsrError {
+    pub(crate) field: &'static str,
+    pub(crate) offset: usize,
+    pub(crate) kind: OsrErrorKind,
+}
+
+#[derive(Debug)]
+pub enum OsrErrorKind {
+    Io(io::Error),
+    UnexpectedEof,
+    InvalidGamemode(u8),
+    InvalidStringMarker(u8),
+    InvalidUtf8(FromUtf8Error),
+    Uleb128Overflow,
+    Lzma(lzma::Error),
+    InvalidFrame { index: usize, reason: String },
+}
+
+impl Display for OsrError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Failed to read {} at byte {}: ", self.field, self.offset)?;
+        match &self.kind {
+            OsrErrorKind::Io(e) => write!(f, "{}", e),
+            OsrErrorKind::UnexpectedEof => write!(f, "unexpected end of file"),
+            OsrErrorKind::InvalidGamemode(value) => write!(f, "invalid gamemode {}", value),
+            OsrErrorKind::InvalidStringMarker(value) => {
+                write!(f, "invalid string marker 0x{:02x}", value)
+            }
+            OsrErrorKind::InvalidUtf8(e) => write!(f, "{}", e),
+            OsrErrorKind::Uleb128Overflow => write!(f, "ULEB128 value does not fit in 64 bits"),
+            OsrErrorKind::Lzma(e) => write!(f, "corrupt LZMA stream: {}", e),
+            OsrErrorKind::InvalidFrame { index, reason } => {
+                write!(f, "invalid frame {}: {}", index, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OsrError {}
+
+impl OsrError {
+    fn new(field: &'static str, offset: usize, kind: OsrErrorKind) -> Self {
+        OsrError {
+            field,
+            offset,
+            kind,
+        }
+    }
+}
+
 impl OsuReplay {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Self {
-        let mut file = File::open(path).unwrap();
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, OsrError> {
+        let file = File::open(path).map_err(|e| OsrError::new("file", 0, OsrErrorKind::Io(e)))?;
+        Self::from_reader(file)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, OsrError> {
         let mut data = Vec::new();
-        file.read_to_end(&mut data).unwrap();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|e| OsrError::new("file", 0, OsrErrorKind::Io(e)))?;
+        Self::from_bytes(&data)
+    }
 
+    pub fn from_bytes(data: &[u8]) -> Result<Self, OsrError> {
         let (gamemode, offset) = {
-            let (byte, offset) = read_u8(&data, 0);
-            (
-                match byte {
-                    0 => Gamemode::Standard,
-                    1 => Gamemode::Taiko,
-                    2 => Gamemode::CatchTheBeat,
-                    3 => Gamemode::Mania,
-                    _ => panic!("Invalid gamemode"),
-                },
-                offset,
-            )
+            let (byte, offset) = read_u8(data, 0, "gamemode")?;
+            let gamemode = Gamemode::try_from(byte)
+                .map_err(|_| OsrError::new("gamemode", 0, OsrErrorKind::InvalidGamemode(byte)))?;
+            (gamemode, offset)
         };
 
-        let (version, offset) = read_u32(&data, offset);
-        let (beatmap_hash, offset) = read_string(&data, offset);
-        let (player_name, offset) = read_string(&data, offset);
-        let (replay_hash, offset) = read_string(&data, offset);
-        let (count_300, offset) = read_u16(&data, offset);
-        let (count_100, offset) = read_u16(&data, offset);
-        let (count_50, offset) = read_u16(&data, offset);
-        let (count_geki, offset) = read_u16(&data, offset);
-        let (count_katu, offset) = read_u16(&data, offset);
-        let (count_miss, offset) = read_u16(&data, offset);
-        let (score, offset) = read_u32(&data, offset);
-        let (max_combo, offset) = read_u16(&data, offset);
-        let (is_perfect_combo, offset) = read_u8(&data, offset);
-        let (mods, offset) = read_u32(&data, offset);
-        let (life_bar_graph, offset) = read_string(&data, offset);
-        let (timestamp, offset) = read_u64(&data, offset);
-        let (replay_data_length, mut offset) = read_u32(&data, offset);
-        let replay_data_compressed = data[offset..offset + replay_data_length as usize].to_vec();
-        offset += replay_data_length as usize;
-
-        let (online_score_id, offset) = read_u64(&data, offset);
+        let (version, offset) = read_u32(data, offset, "version")?;
+        let (beatmap_hash, offset) = read_string(data, offset, "beatmap hash")?;
+        let (player_name, offset) = read_string(data, offset, "player name")?;
+        let (replay_hash, offset) = read_string(data, offset, "replay hash")?;
+        let (count_300, offset) = read_u16(data, offset, "300 count")?;
+        let (count_100, offset) = read_u16(data, offset, "100 count")?;
+        let (count_50, offset) = read_u16(data, offset, "50 count")?;
+        let (count_geki, offset) = read_u16(data, offset, "geki count")?;
+        let (count_katu, offset) = read_u16(data, offset, "katu count")?;
+        let (count_miss, offset) = read_u16(data, offset, "miss count")?;
+        let (score, offset) = read_u32(data, offset, "score")?;
+        let (max_combo, offset) = read_u16(data, offset, "max combo")?;
+        let (is_perfect_combo, offset) = read_u8(data, offset, "perfect combo")?;
+        let (mods, offset) = read_u32(data, offset, "mods")?;
+        let (life_bar_graph, offset) = read_string(data, offset, "life bar graph")?;
+        let life_bar = LifeBarPoint::parse_graph(life_bar_graph.as_deref().unwrap_or_default());
+        let (timestamp, offset) = read_u64(data, offset, "timestamp")?;
+        let (replay_data_length, offset) = read_u32(data, offset, "replay data length")?;
+        let (replay_data_compressed, offset) =
+            read_bytes(data, offset, replay_data_length as usize, "replay data")?;
+        let replay_data_offset = offset - replay_data_compressed.len();
+
+        let (online_score_id, offset) = read_u64(data, offset, "online score id")?;
         let additional_mod_info = if offset < data.len() {
-            let (value, _) = read_u64(&data, offset);
-            Some(value as f64)
+            let (value, _) = read_u64(data, offset, "additional mod info")?;
+            Some(f64::from_bits(value))
         } else {
             None
         };
 
-        let replay_data = ReplayData::from_compressed_stream(replay_data_compressed);
+        let (replay_data, rng_seed) = ReplayData::from_compressed_stream(replay_data_compressed)
+            .map_err(|kind| OsrError::new("replay data", replay_data_offset, kind))?;
 
-        OsuReplay {
+        Ok(OsuReplay {
             gamemode,
             version,
             beatmap_hash,
@@ -98,18 +182,109 @@ impl OsuReplay {
             score,
             max_combo,
             is_perfect_combo: is_perfect_combo == 1,
-            mods,
+            mods: Mods::from_bits(mods),
             life_bar_graph,
+            life_bar,
             timestamp,
             online_score_id,
             additional_mod_info,
             replay_data,
+            rng_seed,
+            replay_data_compressed: replay_data_compressed.to_vec(),
+        })
+    }
+
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.to_writer(BufWriter::new(file))
+    }
+
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes()?)?;
+        writer.flush()
+    }
+
+    /// Encodes the replay as osu! reads it, the inverse of [`OsuReplay::from_bytes`].
+    ///
+    /// Unedited frames keep the bytes they were read from, so a replay that wasn't changed
+    /// is written back byte-for-byte.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        write_u8(&mut data, self.gamemode as u8);
+        write_u32(&mut data, self.version);
+        write_string(&mut data, self.beatmap_hash.as_deref());
+        write_string(&mut data, self.player_name.as_deref());
+        write_string(&mut data, self.replay_hash.as_deref());
+        write_u16(&mut data, self.count_300);
+        write_u16(&mut data, self.count_100);
+        write_u16(&mut data, self.count_50);
+        write_u16(&mut data, self.count_geki);
+        write_u16(&mut data, self.count_katu);
+        write_u16(&mut data, self.count_miss);
+        write_u32(&mut data, self.score);
+        write_u16(&mut data, self.max_combo);
+        write_u8(&mut data, self.is_perfect_combo as u8);
+        write_u32(&mut data, self.mods.bits());
+        write_string(&mut data, self.life_bar_graph.as_deref());
+        write_u64(&mut data, self.timestamp);
+
+        let replay_data_compressed = self.compressed_replay_data()?;
+        write_u32(&mut data, replay_data_compressed.len() as u32);
+        data.extend_from_slice(&replay_data_compressed);
+
+        write_u64(&mut data, self.online_score_id);
+        if let Some(additional_mod_info) = self.additional_mod_info {
+            write_u64(&mut data, additional_mod_info.to_bits());
+        }
+
+        Ok(data)
+    }
+
+    fn compressed_replay_data(&self) -> io::Result<Cow<'_, [u8]>> {
+        let is_unchanged = ReplayData::from_compressed_stream(&self.replay_data_compressed)
+            .is_ok_and(|(replay_data, rng_seed)| {
+                replay_data == self.replay_data && rng_seed == self.rng_seed
+            });
+        if is_unchanged {
+            return Ok(Cow::Borrowed(&self.replay_data_compressed));
         }
+
+        ReplayData::to_compressed_stream(&self.replay_data, self.rng_seed).map(Cow::Owned)
+    }
+}
+
+/// Health shown on the life bar at some point of the play.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LifeBarPoint {
+    /// Milliseconds.
+    pub(crate) time: i64,
+    /// From 0 (failed) to 1 (full).
+    pub(crate) hp: f64,
+}
+
+impl LifeBarPoint {
+    /// Parses the comma separated `time|hp` pairs osu! writes to the replay.
+    ///
+    /// The graph is only shown, so pairs that don't parse are skipped rather than
+    /// failing the whole replay.
+    fn parse_graph(graph: &str) -> Vec<LifeBarPoint> {
+        let mut points = graph
+            .split(',')
+            .filter_map(|piece| {
+                let (time, hp) = piece.trim().split_once('|')?;
+                Some(LifeBarPoint {
+                    time: time.parse().ok()?,
+                    hp: hp.parse().ok()?,
+                })
+            })
+            .collect::<Vec<_>>();
+        points.sort_by_key(|point| point.time);
+        points
     }
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct ReplayData {
     // w 	Long 	Time in milliseconds since the previous action
     pub(crate) time: i64,
@@ -118,6 +293,7 @@ pub struct ReplayData {
     // y 	Float 	y-coordinate of the cursor from 0 - 384
     pub(crate) y: f32,
     pub(crate) keys: i32,
+    /// Milliseconds since the start of the replay, including this frame's delta.
     pub(crate) total_time: u64,
 }
 
@@ -130,97 +306,413 @@ pub enum Keys {
     SMOKE = 16,
 }
 
+const RNG_SEED_FRAME_TIME: i64 = -12345;
+
 impl ReplayData {
-    fn from_compressed_stream(replay_data_compressed: Vec<u8>) -> Vec<ReplayData> {
+    /// Whether a key or mouse button is held down, as sliders and spinners need.
+    pub fn is_holding(&self) -> bool {
+        [Keys::M1, Keys::M2, Keys::K1, Keys::K2]
+            .into_iter()
+            .any(|key| self.keys & key as i32 != 0)
+    }
+
+    fn from_compressed_stream(
+        replay_data_compressed: &[u8],
+    ) -> Result<(Vec<ReplayData>, Option<i32>), OsrErrorKind> {
         let mut data = String::new();
-        lzma::Reader::from(&replay_data_compressed[..])
-            .unwrap()
+        lzma::Reader::from(replay_data_compressed)
+            .map_err(OsrErrorKind::Lzma)?
             .read_to_string(&mut data)
-            .unwrap();
+            .map_err(OsrErrorKind::Io)?;
 
-        let mut total_time = 0;
+        let mut total_time: i64 = 0;
 
-        data.split(",")
+        let mut frames = Vec::new();
+        let mut rng_seed = None;
+        for (index, piece) in data
+            .split(',')
             .filter(|piece| !piece.is_empty())
-            .map(|piece| {
-                let mut parts = piece.split("|");
-                let data = ReplayData {
-                    time: parts.next().unwrap().parse().unwrap(),
-                    x: parts.next().unwrap().parse().unwrap(),
-                    y: parts.next().unwrap().parse().unwrap(),
-                    keys: parts.next().unwrap().parse().unwrap(),
-                    total_time,
-                };
-
-                if data.time >= 0 {
-                    total_time += data.time as u64;
-                }
-
-                data
-            })
-            // Filter out rng seed
-            .filter(|data| data.time != -12345)
-            .collect()
+            .enumerate()
+        {
+            let invalid_frame = |reason: String| OsrErrorKind::InvalidFrame { index, reason };
+            let mut parts = piece.split('|');
+            let mut next_part = |name: &str| {
+                parts
+                    .next()
+                    .ok_or_else(|| invalid_frame(format!("missing {}", name)))
+            };
+            let (time, x, y, keys) = (
+                next_part("time")?,
+                next_part("x")?,
+                next_part("y")?,
+                next_part("keys")?,
+            );
+            let data = ReplayData {
+                time: time
+                    .parse()
+                    .map_err(|e| invalid_frame(format!("time {:?}: {}", time, e)))?,
+                x: x.parse()
+                    .map_err(|e| invalid_frame(format!("x {:?}: {}", x, e)))?,
+                y: y.parse()
+                    .map_err(|e| invalid_frame(format!("y {:?}: {}", y, e)))?,
+                keys: keys
+                    .parse()
+                    .map_err(|e| invalid_frame(format!("keys {:?}: {}", keys, e)))?,
+                total_time: 0,
+            };
+
+            // The rng seed is stored in the keys of a marker frame, not a real frame
+            if data.time == RNG_SEED_FRAME_TIME {
+                rng_seed = Some(data.keys);
+            } else {
+                // a frame happens after its own delta, negative ones included like stable does
+                total_time += data.time;
+                frames.push(ReplayData {
+                    total_time: total_time.max(0) as u64,
+                    ..data
+                });
+            }
+        }
+
+        Ok((frames, rng_seed))
+    }
+
+    fn to_compressed_stream(frames: &[ReplayData], rng_seed: Option<i32>) -> io::Result<Vec<u8>> {
+        let mut data = String::new();
+        for frame in frames {
+            let _ = write!(
+                data,
+                "{}|{}|{}|{},",
+                frame.time, frame.x, frame.y, frame.keys
+            );
+        }
+        if let Some(rng_seed) = rng_seed {
+            let _ = write!(data, "{}|0|0|{},", RNG_SEED_FRAME_TIME, rng_seed);
+        }
+
+        let mut compressed = Vec::new();
+        lzma_rs::lzma_compress_with_options(
+            &mut data.as_bytes(),
+            &mut compressed,
+            &lzma_rs::compress::Options {
+                unpacked_size: lzma_rs::compress::UnpackedSize::WriteToHeader(Some(
+                    data.len() as u64
+                )),
+            },
+        )?;
+        Ok(compressed)
     }
 }
 
-fn read_string(data: &Vec<u8>, offset: usize) -> (String, usize) {
-    if data[offset] == 0 {
-        return (String::new(), offset + 1);
+fn read_bytes<'a>(
+    data: &'a [u8],
+    offset: usize,
+    length: usize,
+    field: &'static str,
+) -> Result<(&'a [u8], usize), OsrError> {
+    let end = offset
+        .checked_add(length)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| OsrError::new(field, offset, OsrErrorKind::UnexpectedEof))?;
+    Ok((&data[offset..end], end))
+}
+
+fn read_string(
+    data: &[u8],
+    offset: usize,
+    field: &'static str,
+) -> Result<(Option<String>, usize), OsrError> {
+    let (marker, offset_length) = read_u8(data, offset, field)?;
+    match marker {
+        0x00 => return Ok((None, offset_length)),
+        0x0b => {}
+        _ => {
+            return Err(OsrError::new(
+                field,
+                offset,
+                OsrErrorKind::InvalidStringMarker(marker),
+            ))
+        }
     }
 
-    let offset_length = offset + 1;
-    let (length, offset_str) = read_uleb128(data, offset_length);
-    let offset_end = offset_str + length as usize;
-    let string = String::from_utf8(data[offset_str..offset_end].to_vec()).unwrap();
-    (string, offset_end)
+    let (length, offset_str) = read_uleb128(data, offset_length, field)?;
+    let (bytes, offset_end) = read_bytes(data, offset_str, length as usize, field)?;
+    let string = String::from_utf8(bytes.to_vec())
+        .map_err(|e| OsrError::new(field, offset_str, OsrErrorKind::InvalidUtf8(e)))?;
+    Ok((Some(string), offset_end))
 }
 
-fn read_uleb128(data: &Vec<u8>, offset: usize) -> (u64, usize) {
+fn read_uleb128(data: &[u8], offset: usize, field: &'static str) -> Result<(u64, usize), OsrError> {
     let mut result = 0;
     let mut shift = 0;
     let mut end = offset;
     loop {
-        let byte = data[end];
-        end += 1;
+        if shift >= 64 {
+            return Err(OsrError::new(field, offset, OsrErrorKind::Uleb128Overflow));
+        }
+        let (byte, next) = read_u8(data, end, field)?;
+        end = next;
         result |= ((byte & 0x7F) as u64) << shift;
         if byte & 0x80 == 0 {
             break;
         }
         shift += 7;
     }
-    (result, end)
+    Ok((result, end))
+}
+
+fn read_array<const N: usize>(
+    data: &[u8],
+    offset: usize,
+    field: &'static str,
+) -> Result<([u8; N], usize), OsrError> {
+    let (bytes, end) = read_bytes(data, offset, N, field)?;
+    Ok((bytes.try_into().unwrap(), end))
+}
+
+fn read_u8(data: &[u8], offset: usize, field: &'static str) -> Result<(u8, usize), OsrError> {
+    let (bytes, end) = read_array::<1>(data, offset, field)?;
+    Ok((bytes[0], end))
+}
+
+fn read_u16(data: &[u8], offset: usize, field: &'static str) -> Result<(u16, usize), OsrError> {
+    let (bytes, end) = read_array(data, offset, field)?;
+    Ok((u16::from_le_bytes(bytes), end))
+}
+
+fn read_u32(data: &[u8], offset: usize, field: &'static str) -> Result<(u32, usize), OsrError> {
+    let (bytes, end) = read_array(data, offset, field)?;
+    Ok((u32::from_le_bytes(bytes), end))
+}
+
+fn read_u64(data: &[u8], offset: usize, field: &'static str) -> Result<(u64, usize), OsrError> {
+    let (bytes, end) = read_array(data, offset, field)?;
+    Ok((u64::from_le_bytes(bytes), end))
+}
+
+fn write_string(data: &mut Vec<u8>, value: Option<&str>) {
+    let Some(value) = value else {
+        write_u8(data, 0x00);
+        return;
+    };
+
+    write_u8(data, 0x0b);
+    write_uleb128(data, value.len() as u64);
+    data.extend_from_slice(value.as_bytes());
+}
+
+fn write_uleb128(data: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            break;
+        }
+        data.push(byte | 0x80);
+    }
 }
 
-fn read_u8(data: &Vec<u8>, offset: usize) -> (u8, usize) {
-    (data[offset], offset + 1)
+fn write_u8(data: &mut Vec<u8>, value: u8) {
+    data.push(value);
 }
 
-fn read_u16(data: &Vec<u8>, offset: usize) -> (u16, usize) {
-    let value = u16::from_le_bytes([data[offset], data[offset + 1]]);
-    (value, offset + 2)
+fn write_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_le_bytes());
 }
 
-fn read_u32(data: &Vec<u8>, offset: usize) -> (u32, usize) {
-    let value = u32::from_le_bytes([
-        data[offset],
-        data[offset + 1],
-        data[offset + 2],
-        data[offset + 3],
-    ]);
-    (value, offset + 4)
+fn write_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
 }
 
-fn read_u64(data: &Vec<u8>, offset: usize) -> (u64, usize) {
-    let value = u64::from_le_bytes([
-        data[offset],
-        data[offset + 1],
-        data[offset + 2],
-        data[offset + 3],
-        data[offset + 4],
-        data[offset + 5],
-        data[offset + 6],
-        data[offset + 7],
-    ]);
-    (value, offset + 8)
+fn write_u64(data: &mut Vec<u8>, value: u64) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `0|256|-500|0,-1|256|-500|0,1000|100.5|200|1,16|101.25|201|5,-12345|0|0|42,` the way
+    /// osu! compresses frames: LZMA-alone with a 2 MiB dictionary and an unknown size, which
+    /// isn't what we compress to.
+    const OSU_FRAMES: [u8; 75] = [
+        0x5d, 0x00, 0x00, 0x20, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x18,
+        0x1f, 0x02, 0x43, 0x51, 0x03, 0xb4, 0x00, 0x55, 0x57, 0xd8, 0x53, 0xab, 0x04, 0x8d, 0x68,
+        0x02, 0x8a, 0x8f, 0xa4, 0x69, 0xf0, 0xe0, 0x92, 0x1b, 0x19, 0xa2, 0x8a, 0x93, 0x33, 0xea,
+        0x18, 0xa7, 0x05, 0xd2, 0xaf, 0xd6, 0xb1, 0x66, 0x8a, 0xb7, 0x82, 0x44, 0x9a, 0x90, 0xe6,
+        0x3a, 0xb1, 0x64, 0x7b, 0x0a, 0xe4, 0xe2, 0xb0, 0x49, 0xdf, 0xff, 0x4f, 0xa0, 0xc0, 0x00,
+    ];
+
+    /// A replay as osu! writes it, with an empty player name and no replay hash.
+    fn fixture() -> Vec<u8> {
+        with_frames(&OSU_FRAMES)
+    }
+
+    fn with_frames(frames: &[u8]) -> Vec<u8> {
+        let mut data = vec![0];
+        data.extend(20240101u32.to_le_bytes());
+        write_string(&mut data, Some("d41d8cd98f00b204e9800998ecf8427e"));
+        write_string(&mut data, Some(""));
+        write_string(&mut data, None);
+        for count in [100u16, 2, 1, 10, 3, 0] {
+            data.extend(count.to_le_bytes());
+        }
+        data.extend(123456u32.to_le_bytes());
+        data.extend(200u16.to_le_bytes());
+        data.push(1);
+        data.extend((Mods::HIDDEN | Mods::DOUBLE_TIME).bits().to_le_bytes());
+        write_string(&mut data, Some("0|1,1000|0.5,"));
+        data.extend(638400000000000000u64.to_le_bytes());
+        data.extend((frames.len() as u32).to_le_bytes());
+        data.extend(frames);
+        data.extend(99u64.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn reads_fixture() {
+        let replay = OsuReplay::from_bytes(&fixture()).unwrap();
+        assert_eq!(replay.player_name.as_deref(), Some(""));
+        assert_eq!(replay.replay_hash, None);
+        assert_eq!(replay.rng_seed, Some(42));
+        assert_eq!(replay.replay_data.len(), 4);
+        assert_eq!(replay.replay_data[3].total_time, 1015);
+        assert_eq!(replay.additional_mod_info, None);
+    }
+
+    /// Where the frames start in [`fixture`].
+    fn frames_offset() -> usize {
+        fixture().len() - 8 - OSU_FRAMES.len()
+    }
+
+    fn error(data: &[u8]) -> OsrError {
+        match OsuReplay::from_bytes(data) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn reports_truncated_header() {
+        let e = error(&fixture()[..3]);
+        assert_eq!((e.field, e.offset), ("version", 1));
+        assert!(matches!(e.kind, OsrErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn reports_invalid_gamemode() {
+        let mut data = fixture();
+        data[0] = 7;
+        let e = error(&data);
+        assert_eq!((e.field, e.offset), ("gamemode", 0));
+        assert!(matches!(e.kind, OsrErrorKind::InvalidGamemode(7)));
+    }
+
+    #[test]
+    fn reports_invalid_string_marker() {
+        let mut data = fixture();
+        data[5] = 0x0c;
+        let e = error(&data);
+        assert_eq!((e.field, e.offset), ("beatmap hash", 5));
+        assert!(matches!(e.kind, OsrErrorKind::InvalidStringMarker(0x0c)));
+    }
+
+    #[test]
+    fn reports_invalid_utf8() {
+        // the hash starts after its marker and length
+        let mut data = fixture();
+        data[7] = 0xff;
+        let e = error(&data);
+        assert_eq!((e.field, e.offset), ("beatmap hash", 7));
+        assert!(matches!(e.kind, OsrErrorKind::InvalidUtf8(_)));
+    }
+
+    #[test]
+    fn reports_corrupt_lzma() {
+        let mut frames = OSU_FRAMES;
+        // lc, lp and pb can't add up to this
+        frames[0] = 0xff;
+        let e = error(&with_frames(&frames));
+        assert_eq!((e.field, e.offset), ("replay data", frames_offset()));
+        assert!(matches!(e.kind, OsrErrorKind::Lzma(_)));
+    }
+
+    #[test]
+    fn reports_malformed_frame() {
+        let mut frames = Vec::new();
+        lzma_rs::lzma_compress(&mut &b"0|256|-500|0,16|1|2|x,"[..], &mut frames).unwrap();
+        let e = error(&with_frames(&frames));
+        assert_eq!((e.field, e.offset), ("replay data", frames_offset()));
+        match e.kind {
+            OsrErrorKind::InvalidFrame { index, reason } => {
+                assert_eq!(index, 1);
+                assert!(reason.starts_with("keys"), "{}", reason);
+            }
+            kind => panic!("expected an invalid frame, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn skips_broken_life_bar_points() {
+        let points = LifeBarPoint::parse_graph("2000|0.5, 0|1,x|1,3000,4000|?,1000|0.75,");
+        assert_eq!(
+            points,
+            [
+                LifeBarPoint { time: 0, hp: 1.0 },
+                LifeBarPoint {
+                    time: 1000,
+                    hp: 0.75
+                },
+                LifeBarPoint {
+                    time: 2000,
+                    hp: 0.5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_unchanged_replay_back_byte_for_byte() {
+        let data = fixture();
+        let replay = OsuReplay::from_bytes(&data).unwrap();
+        assert_eq!(replay.to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn round_trips_edited_frames() {
+        let data = fixture();
+        let mut replay = OsuReplay::from_bytes(&data).unwrap();
+        replay.player_name = None;
+        replay.replay_data[2].keys = 0;
+        replay.replay_data.push(ReplayData {
+            time: 16,
+            x: 0.1,
+            y: -3.75,
+            keys: 10,
+            total_time: 1031,
+        });
+        replay.rng_seed = Some(-7);
+        replay.additional_mod_info = Some(1.5);
+
+        let edited = replay.to_bytes().unwrap();
+        assert_ne!(edited, data);
+        let decoded = OsuReplay::from_bytes(&edited).unwrap();
+        assert_eq!(decoded.player_name, None);
+        assert_eq!(decoded.replay_data, replay.replay_data);
+        assert_eq!(decoded.rng_seed, Some(-7));
+        assert_eq!(decoded.additional_mod_info, Some(1.5));
+        assert_eq!(decoded.to_bytes().unwrap(), edited);
+    }
+
+    #[test]
+    fn writes_files() {
+        let data = fixture();
+        let replay = OsuReplay::from_bytes(&data).unwrap();
+        let path = std::env::temp_dir().join("rewind-rs-round-trip.osr");
+        replay.to_file(&path).unwrap();
+        let written = std::fs::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.unwrap(), data);
+    }
 }
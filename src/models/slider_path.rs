@@ -0,0 +1,400 @@
+use std::f64::consts::PI;
+
+use super::osu_map::SliderCurveType;
+
+type Point = (f64, f64);
+
+const BEZIER_TOLERANCE: f64 = 0.25;
+const CATMULL_DETAIL: usize = 50;
+const CIRCULAR_ARC_TOLERANCE: f64 = 0.1;
+
+/// A slider's path flattened into a polyline, cut or extended to the slider's pixel length.
+#[derive(Debug, Default, Clone)]
+pub struct SliderPath {
+    pub(crate) points: Vec<Point>,
+    /// Distance along the path at each point.
+    lengths: Vec<f64>,
+}
+
+impl SliderPath {
+    pub fn new(
+        start: Point,
+        curve_type: &SliderCurveType,
+        curve_points: &[Point],
+        pixel_length: f64,
+    ) -> Self {
+        let control_points: Vec<Point> = std::iter::once(start)
+            .chain(curve_points.iter().copied())
+            .collect();
+
+        let points = match curve_type {
+            SliderCurveType::Linear => control_points,
+            SliderCurveType::PerfectCircle => {
+                circular_arc(&control_points).unwrap_or_else(|| bezier(&control_points))
+            }
+            SliderCurveType::Bezier => bezier(&control_points),
+            SliderCurveType::Catmull => catmull(&control_points),
+        };
+
+        let mut path = SliderPath {
+            points,
+            lengths: Vec::new(),
+        };
+        path.fit_to_length(pixel_length);
+        path
+    }
+
+    pub fn length(&self) -> f64 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    pub fn end_position(&self) -> Point {
+        self.points.last().copied().unwrap_or_default()
+    }
+
+    /// Position at `progress` along the path, 0 being the head and 1 the end.
+    pub fn position_at(&self, progress: f64) -> Point {
+        self.position_at_distance(progress.clamp(0.0, 1.0) * self.length())
+    }
+
+    pub fn position_at_distance(&self, distance: f64) -> Point {
+        if self.points.len() < 2 {
+            return self.end_position();
+        }
+
+        let index = self
+            .lengths
+            .partition_point(|length| *length < distance)
+            .clamp(1, self.points.len() - 1);
+        let (start, end) = (self.lengths[index - 1], self.lengths[index]);
+        let t = if end > start {
+            (distance - start) / (end - start)
+        } else {
+            0.0
+        };
+        lerp(
+            self.points[index - 1],
+            self.points[index],
+            t.clamp(0.0, 1.0),
+        )
+    }
+
+    /// Cuts the path at `pixel_length` or extends its last segment in a straight line,
+    /// which is what osu! does when the control points don't match the length.
+    fn fit_to_length(&mut self, pixel_length: f64) {
+        self.points.dedup();
+        self.lengths = cumulative_lengths(&self.points);
+
+        let calculated_length = self.length();
+        if pixel_length <= 0.0 || self.points.len() < 2 {
+            return;
+        }
+
+        if calculated_length > pixel_length {
+            let index = self
+                .lengths
+                .partition_point(|length| *length < pixel_length);
+            let end = self.position_at_distance(pixel_length);
+            self.points.truncate(index);
+            self.points.push(end);
+        } else if calculated_length < pixel_length {
+            let count = self.points.len();
+            let (previous, last) = (self.points[count - 2], self.points[count - 1]);
+            let segment_length = distance(previous, last);
+            let remaining = pixel_length - self.lengths[count - 2];
+            self.points[count - 1] = lerp(previous, last, remaining / segment_length);
+        }
+
+        self.lengths = cumulative_lengths(&self.points);
+    }
+}
+
+fn cumulative_lengths(points: &[Point]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut lengths = Vec::with_capacity(points.len());
+    for (index, point) in points.iter().enumerate() {
+        if index > 0 {
+            total += distance(points[index - 1], *point);
+        }
+        lengths.push(total);
+    }
+    lengths
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+fn lerp(a: Point, b: Point, t: f64) -> Point {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+/// Arc through exactly three points, `None` if the points don't describe one.
+fn circular_arc(control_points: &[Point]) -> Option<Vec<Point>> {
+    let [a, b, c] = control_points else {
+        return None;
+    };
+
+    let determinant = 2.0 * (a.0 * (b.1 - c.1) + b.0 * (c.1 - a.1) + c.0 * (a.1 - b.1));
+    if determinant.abs() < 1e-3 {
+        return None;
+    }
+
+    let a_squared = a.0 * a.0 + a.1 * a.1;
+    let b_squared = b.0 * b.0 + b.1 * b.1;
+    let c_squared = c.0 * c.0 + c.1 * c.1;
+    let center = (
+        (a_squared * (b.1 - c.1) + b_squared * (c.1 - a.1) + c_squared * (a.1 - b.1)) / determinant,
+        (a_squared * (c.0 - b.0) + b_squared * (a.0 - c.0) + c_squared * (b.0 - a.0)) / determinant,
+    );
+    let radius = distance(*a, center);
+
+    let theta_start = (a.1 - center.1).atan2(a.0 - center.0);
+    let mut theta_end = (c.1 - center.1).atan2(c.0 - center.0);
+    while theta_end < theta_start {
+        theta_end += 2.0 * PI;
+    }
+
+    let mut direction = 1.0;
+    let mut theta_range = theta_end - theta_start;
+
+    // Draw the arc on the side of the chord A-C that B lies on
+    let ortho_a_to_c = (c.1 - a.1, -(c.0 - a.0));
+    if ortho_a_to_c.0 * (b.0 - a.0) + ortho_a_to_c.1 * (b.1 - a.1) < 0.0 {
+        direction = -direction;
+        theta_range = 2.0 * PI - theta_range;
+    }
+
+    let point_count = if 2.0 * radius <= CIRCULAR_ARC_TOLERANCE {
+        2
+    } else {
+        let step = 2.0 * (1.0 - CIRCULAR_ARC_TOLERANCE / radius).acos();
+        ((theta_range / step).ceil() as usize).max(2)
+    };
+
+    Some(
+        (0..point_count)
+            .map(|index| {
+                let fraction = index as f64 / (point_count - 1) as f64;
+                let theta = theta_start + direction * fraction * theta_range;
+                (
+                    center.0 + theta.cos() * radius,
+                    center.1 + theta.sin() * radius,
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Bezier curves start a new segment at every repeated control point (red anchors).
+fn bezier(control_points: &[Point]) -> Vec<Point> {
+    let mut points = Vec::new();
+    let mut segment_start = 0;
+    for index in 1..=control_points.len() {
+        let segment_end =
+            index == control_points.len() || control_points[index] == control_points[index - 1];
+        if segment_end {
+            points.extend(bezier_segment(&control_points[segment_start..index]));
+            segment_start = index;
+        }
+    }
+    points
+}
+
+fn bezier_segment(control_points: &[Point]) -> Vec<Point> {
+    match control_points {
+        [] => Vec::new(),
+        [_] | [_, _] => control_points.to_vec(),
+        _ => {
+            let mut points = Vec::new();
+            let mut to_flatten = vec![control_points.to_vec()];
+            while let Some(curve) = to_flatten.pop() {
+                if is_flat_enough(&curve) {
+                    points.push(curve[0]);
+                    continue;
+                }
+                let (left, right) = subdivide(&curve);
+                to_flatten.push(right);
+                to_flatten.push(left);
+            }
+            points.push(control_points[control_points.len() - 1]);
+            points
+        }
+    }
+}
+
+fn is_flat_enough(curve: &[Point]) -> bool {
+    curve.windows(3).all(|window| {
+        let x = window[0].0 - 2.0 * window[1].0 + window[2].0;
+        let y = window[0].1 - 2.0 * window[1].1 + window[2].1;
+        x * x + y * y <= BEZIER_TOLERANCE * BEZIER_TOLERANCE * 4.0
+    })
+}
+
+/// Splits a bezier curve in half with de Casteljau's algorithm.
+fn subdivide(curve: &[Point]) -> (Vec<Point>, Vec<Point>) {
+    let mut left = Vec::with_capacity(curve.len());
+    let mut right = Vec::with_capacity(curve.len());
+    let mut midpoints = curve.to_vec();
+    while !midpoints.is_empty() {
+        left.push(midpoints[0]);
+        right.push(midpoints[midpoints.len() - 1]);
+        midpoints = midpoints
+            .windows(2)
+            .map(|window| lerp(window[0], window[1], 0.5))
+            .collect();
+    }
+    right.reverse();
+    (left, right)
+}
+
+fn catmull(control_points: &[Point]) -> Vec<Point> {
+    let mut points = Vec::new();
+    for index in 0..control_points.len().saturating_sub(1) {
+        let v2 = control_points[index];
+        let v1 = if index > 0 {
+            control_points[index - 1]
+        } else {
+            v2
+        };
+        let v3 = control_points[index + 1];
+        let v4 = control_points
+            .get(index + 2)
+            .copied()
+            .unwrap_or((2.0 * v3.0 - v2.0, 2.0 * v3.1 - v2.1));
+
+        for step in 0..CATMULL_DETAIL {
+            let t = step as f64 / CATMULL_DETAIL as f64;
+            points.push(catmull_point(v1, v2, v3, v4, t));
+        }
+    }
+    points.extend(control_points.last());
+    points
+}
+
+fn catmull_point(v1: Point, v2: Point, v3: Point, v4: Point, t: f64) -> Point {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let component = |p1: f64, p2: f64, p3: f64, p4: f64| {
+        0.5 * (2.0 * p2
+            + (-p1 + p3) * t
+            + (2.0 * p1 - 5.0 * p2 + 4.0 * p3 - p4) * t2
+            + (-p1 + 3.0 * p2 - 3.0 * p3 + p4) * t3)
+    };
+    (
+        component(v1.0, v2.0, v3.0, v4.0),
+        component(v1.1, v2.1, v3.1, v4.1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Point, expected: Point, tolerance: f64) {
+        assert!(
+            distance(actual, expected) <= tolerance,
+            "expected {:?} to be near {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn draws_perfect_circles_through_the_middle_point() {
+        let path = SliderPath::new(
+            (0.0, 0.0),
+            &SliderCurveType::PerfectCircle,
+            &[(100.0, 100.0), (200.0, 0.0)],
+            100.0 * PI,
+        );
+        let center = (100.0, 0.0);
+        for point in &path.points[..path.points.len() - 1] {
+            assert!((distance(*point, center) - 100.0).abs() < 1e-9);
+            assert!(point.1 >= -1e-9);
+        }
+        assert!((path.length() - 100.0 * PI).abs() < 1e-9);
+        assert_near(path.position_at(0.5), (100.0, 100.0), 0.5);
+        assert_near(path.end_position(), (200.0, 0.0), 0.5);
+    }
+
+    #[test]
+    fn draws_perfect_circles_without_an_arc_as_bezier() {
+        let path = SliderPath::new(
+            (0.0, 0.0),
+            &SliderCurveType::PerfectCircle,
+            &[(50.0, 0.0), (100.0, 0.0)],
+            100.0,
+        );
+        assert!(path.points.iter().all(|point| point.1 == 0.0));
+        assert_eq!(path.end_position(), (100.0, 0.0));
+        assert!((path.length() - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn splits_bezier_curves_at_red_anchors() {
+        let path = SliderPath::new(
+            (0.0, 0.0),
+            &SliderCurveType::Bezier,
+            &[(100.0, 0.0), (100.0, 0.0), (100.0, 100.0)],
+            200.0,
+        );
+        // two straight segments meeting at the corner instead of one curve cutting it
+        assert_eq!(path.points, [(0.0, 0.0), (100.0, 0.0), (100.0, 100.0)]);
+        assert_eq!(path.position_at(0.5), (100.0, 0.0));
+    }
+
+    #[test]
+    fn flattens_bezier_curves() {
+        let path = SliderPath::new(
+            (0.0, 0.0),
+            &SliderCurveType::Bezier,
+            &[(100.0, 100.0), (200.0, 0.0)],
+            0.0,
+        );
+        assert!(path.points.len() > 3);
+        assert_eq!(path.points[0], (0.0, 0.0));
+        assert_eq!(path.end_position(), (200.0, 0.0));
+        // a quadratic curve only gets half way to its middle control point
+        assert_near(path.position_at(0.5), (100.0, 50.0), 0.5);
+    }
+
+    #[test]
+    fn draws_catmull_curves_through_their_control_points() {
+        let path = SliderPath::new(
+            (0.0, 0.0),
+            &SliderCurveType::Catmull,
+            &[(100.0, 100.0), (200.0, 0.0)],
+            0.0,
+        );
+        assert_eq!(path.points.len(), 2 * CATMULL_DETAIL + 1);
+        assert_eq!(path.points[0], (0.0, 0.0));
+        assert_eq!(path.points[CATMULL_DETAIL], (100.0, 100.0));
+        assert_eq!(path.end_position(), (200.0, 0.0));
+    }
+
+    #[test]
+    fn cuts_paths_longer_than_the_pixel_length() {
+        let path = SliderPath::new(
+            (0.0, 0.0),
+            &SliderCurveType::Linear,
+            &[(100.0, 0.0), (100.0, 100.0)],
+            150.0,
+        );
+        assert_eq!(path.points, [(0.0, 0.0), (100.0, 0.0), (100.0, 50.0)]);
+        assert_eq!(path.length(), 150.0);
+    }
+
+    #[test]
+    fn extends_paths_shorter_than_the_pixel_length() {
+        let path = SliderPath::new(
+            (0.0, 0.0),
+            &SliderCurveType::Linear,
+            &[(100.0, 0.0), (100.0, 100.0)],
+            250.0,
+        );
+        assert_eq!(path.points, [(0.0, 0.0), (100.0, 0.0), (100.0, 150.0)]);
+        assert_eq!(path.length(), 250.0);
+        assert_eq!(path.position_at(0.2), (50.0, 0.0));
+    }
+}
@@ -0,0 +1,207 @@
+use std::f64::consts::PI;
+
+use super::{
+    osu_map::{Difficulty, Spinner, PLAYFIELD_CENTER},
+    osu_replay::ReplayData,
+};
+
+/// osu! caps spinning at roughly 477 RPM.
+const MAX_ROTATIONS_PER_SECOND: f64 = 8.0;
+/// Spins after clearing that don't count towards the bonus yet.
+const BONUS_SPINS_GAP: u32 = 2;
+const RPM_WINDOW: f64 = 500.0;
+
+/// How far the player got spinning a spinner, computed from the cursor frames.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpinProgress {
+    /// Total rotation in radians while a key was held.
+    pub(crate) rotation: f64,
+    pub(crate) rpm: f64,
+    pub(crate) required_spins: u32,
+    pub(crate) max_bonus_spins: u32,
+}
+
+impl SpinProgress {
+    /// Spin progress of a spinner starting at `start_time` up to `time`, both in milliseconds.
+    pub fn compute(
+        start_time: f64,
+        spinner: &Spinner,
+        difficulty: &Difficulty,
+        frames: &[ReplayData],
+        time: f64,
+    ) -> Self {
+        let end_time = (spinner.end_time as f64).min(time);
+        let duration_seconds = (spinner.end_time as f64 - start_time).max(0.0) / 1000.0;
+
+        let od = difficulty.overall_difficulty.value;
+        let spins_per_second = 0.6
+            * if od < 5.0 {
+                3.0 + 0.4 * od
+            } else {
+                2.5 + 0.5 * od
+            };
+        let required_spins = (duration_seconds * spins_per_second) as u32;
+        let max_bonus_spins = (((MAX_ROTATIONS_PER_SECOND - spins_per_second) * duration_seconds)
+            as u32)
+            .saturating_sub(BONUS_SPINS_GAP);
+
+        let mut rotation = 0.0;
+        // (time, rotation) after every frame, to measure the RPM
+        let mut history = Vec::new();
+        let mut last: Option<&ReplayData> = None;
+        for frame in frames {
+            let frame_time = frame.total_time as f64;
+            if frame_time < start_time {
+                last = Some(frame);
+                continue;
+            }
+            if frame_time > end_time {
+                break;
+            }
+
+            if let Some(last) = last {
+                if frame.is_holding() {
+                    let delta = angle_of(frame) - angle_of(last);
+                    // take the shortest way around
+                    let delta = (delta + PI).rem_euclid(2.0 * PI) - PI;
+                    let elapsed = frame_time - (last.total_time as f64).max(start_time);
+                    let max_delta = MAX_ROTATIONS_PER_SECOND * 2.0 * PI * elapsed / 1000.0;
+                    rotation += delta.abs().min(max_delta);
+                }
+            }
+            history.push((frame_time, rotation));
+            last = Some(frame);
+        }
+
+        let rpm = history
+            .iter()
+            .find(|(frame_time, _)| *frame_time >= end_time - RPM_WINDOW)
+            .map_or(0.0, |(window_start, rotation_at_window_start)| {
+                let window = (end_time - window_start).max(1.0);
+                (rotation - rotation_at_window_start) / (2.0 * PI) * 60000.0 / window
+            });
+
+        SpinProgress {
+            rotation,
+            rpm,
+            required_spins,
+            max_bonus_spins,
+        }
+    }
+
+    pub fn spins(&self) -> f64 {
+        self.rotation / (2.0 * PI)
+    }
+
+    /// Share of the required spins done, 1 once the spinner is cleared.
+    pub fn completion(&self) -> f64 {
+        if self.required_spins == 0 {
+            1.0
+        } else {
+            (self.spins() / self.required_spins as f64).min(1.0)
+        }
+    }
+
+    pub fn is_cleared(&self) -> bool {
+        self.completion() >= 1.0
+    }
+
+    pub fn bonus_spins(&self) -> u32 {
+        (self.spins() as u32)
+            .saturating_sub(self.required_spins + BONUS_SPINS_GAP)
+            .min(self.max_bonus_spins)
+    }
+}
+
+fn angle_of(frame: &ReplayData) -> f64 {
+    (frame.y as f64 - PLAYFIELD_CENTER.1).atan2(frame.x as f64 - PLAYFIELD_CENTER.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{osu_map::OsuMap, osu_replay::Keys};
+
+    const SPINNER: Spinner = Spinner { end_time: 3000 };
+
+    fn difficulty(od: f64) -> Difficulty {
+        let beatmap: OsuMap = format!("[Difficulty]\nOverallDifficulty:{}\n", od)
+            .parse()
+            .unwrap();
+        beatmap.difficulty
+    }
+
+    /// A frame every 16 ms from the spinner's start, each `step` radians further around
+    /// the middle of the playfield.
+    fn spin(count: usize, step: f64, keys: i32) -> Vec<ReplayData> {
+        (0..count)
+            .map(|i| {
+                let angle = i as f64 * step;
+                ReplayData {
+                    time: 16,
+                    x: (PLAYFIELD_CENTER.0 + 100.0 * angle.cos()) as f32,
+                    y: (PLAYFIELD_CENTER.1 + 100.0 * angle.sin()) as f32,
+                    keys,
+                    total_time: 1000 + i as u64 * 16,
+                }
+            })
+            .collect()
+    }
+
+    fn progress(od: f64, frames: &[ReplayData]) -> SpinProgress {
+        SpinProgress::compute(1000.0, &SPINNER, &difficulty(od), frames, 3000.0)
+    }
+
+    #[test]
+    fn counts_rotations_both_ways() {
+        // eight frames a turn is just under the cap
+        let clockwise = progress(5.0, &spin(81, PI / 4.0, Keys::K1 as i32));
+        assert!((clockwise.spins() - 10.0).abs() < 1e-4);
+        let counter_clockwise = progress(5.0, &spin(81, -PI / 4.0, Keys::M2 as i32));
+        assert!((counter_clockwise.spins() - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn caps_the_rotation_speed() {
+        let progress = progress(5.0, &spin(81, PI * 0.9, Keys::K1 as i32));
+        let max_spins = MAX_ROTATIONS_PER_SECOND * 80.0 * 16.0 / 1000.0;
+        assert!((progress.spins() - max_spins).abs() < 1e-4);
+    }
+
+    #[test]
+    fn only_counts_rotations_while_a_key_is_held() {
+        let mut frames = spin(81, PI / 4.0, 0);
+        assert_eq!(progress(5.0, &frames).spins(), 0.0);
+
+        // smoke doesn't spin either
+        for frame in &mut frames[..41] {
+            frame.keys = Keys::SMOKE as i32;
+        }
+        for frame in &mut frames[41..] {
+            frame.keys = Keys::M1 as i32;
+        }
+        assert!((progress(5.0, &frames).spins() - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn requires_more_spins_on_higher_od() {
+        // two seconds at 1.8, 3 and 4.5 spins a second
+        assert_eq!(progress(0.0, &[]).required_spins, 3);
+        assert_eq!(progress(5.0, &[]).required_spins, 6);
+        assert_eq!(progress(10.0, &[]).required_spins, 9);
+        assert_eq!(progress(5.0, &[]).max_bonus_spins, 8);
+    }
+
+    #[test]
+    fn counts_bonus_spins_after_clearing() {
+        // 5 of 6 spins
+        let short = progress(5.0, &spin(41, PI / 4.0, Keys::K1 as i32));
+        assert!(!short.is_cleared());
+        assert_eq!(short.bonus_spins(), 0);
+
+        // 10.5 spins, the first two past the required ones don't count
+        let long = progress(5.0, &spin(85, PI / 4.0, Keys::K1 as i32));
+        assert!(long.is_cleared());
+        assert_eq!(long.bonus_spins(), 2);
+    }
+}
@@ -0,0 +1,241 @@
+use super::osu_map::{HitObject, HitType, OsuMap};
+
+/// Objects closer than this in osu! pixels stack on each other.
+const STACK_DISTANCE: f64 = 3.0;
+
+impl OsuMap {
+    /// Offsets objects that would be drawn on top of each other like osu!stable does,
+    /// using the stable algorithm for maps from v6 on and the old one before.
+    ///
+    /// Depends on CS and AR, so it runs again when mods are applied.
+    pub(crate) fn apply_stacking(&mut self) {
+        for hit_object in &mut self.hit_objects {
+            hit_object.stack_height = 0;
+        }
+
+        if self.format_version >= 6 {
+            self.apply_stacking_stable();
+        } else {
+            self.apply_stacking_old();
+        }
+
+        // each stacked object moves up and to the left by a tenth of a circle
+        let stack_offset = self.difficulty.circle_radius() / 10.0;
+        for hit_object in &mut self.hit_objects {
+            let offset = hit_object.stack_height as f64 * stack_offset;
+            hit_object.stacked_position =
+                (hit_object.x as f64 - offset, hit_object.y as f64 - offset);
+        }
+    }
+
+    fn stack_threshold(&self) -> f64 {
+        self.difficulty.approach_rate.preempt * 1000.0 * self.general.stack_leniency
+    }
+
+    fn apply_stacking_stable(&mut self) {
+        let stack_threshold = self.stack_threshold();
+        let objects = &mut self.hit_objects;
+        if objects.is_empty() {
+            return;
+        }
+
+        for i in (1..objects.len()).rev() {
+            let mut n = i;
+            let mut object_i = i;
+            if objects[object_i].stack_height != 0 || is_spinner(&objects[object_i]) {
+                continue;
+            }
+
+            if let HitType::Circle = objects[object_i].hit_type {
+                while n > 0 {
+                    n -= 1;
+                    if is_spinner(&objects[n]) {
+                        continue;
+                    }
+                    if objects[object_i].time as f64 - objects[n].end_time() > stack_threshold {
+                        break;
+                    }
+
+                    // objects ending on a slider end stack down and to the right of it instead
+                    if is_slider(&objects[n])
+                        && distance(end_position(&objects[n]), raw_position(&objects[object_i]))
+                            < STACK_DISTANCE
+                    {
+                        let offset = objects[object_i].stack_height - objects[n].stack_height + 1;
+                        let slider_end = end_position(&objects[n]);
+                        for object_j in &mut objects[n + 1..=i] {
+                            if distance(slider_end, raw_position(object_j)) < STACK_DISTANCE {
+                                object_j.stack_height -= offset;
+                            }
+                        }
+                        break;
+                    }
+
+                    if distance(raw_position(&objects[n]), raw_position(&objects[object_i]))
+                        < STACK_DISTANCE
+                    {
+                        objects[n].stack_height = objects[object_i].stack_height + 1;
+                        object_i = n;
+                    }
+                }
+            } else if is_slider(&objects[object_i]) {
+                while n > 0 {
+                    n -= 1;
+                    if is_spinner(&objects[n]) {
+                        continue;
+                    }
+                    if objects[object_i].time as f64 - objects[n].time as f64 > stack_threshold {
+                        break;
+                    }
+
+                    if distance(end_position(&objects[n]), raw_position(&objects[object_i]))
+                        < STACK_DISTANCE
+                    {
+                        objects[n].stack_height = objects[object_i].stack_height + 1;
+                        object_i = n;
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply_stacking_old(&mut self) {
+        let stack_threshold = self.stack_threshold();
+        let objects = &mut self.hit_objects;
+
+        for i in 0..objects.len() {
+            if objects[i].stack_height != 0 && !is_slider(&objects[i]) {
+                continue;
+            }
+
+            let mut start_time = objects[i].end_time();
+            let mut slider_stack = 0;
+            let position = raw_position(&objects[i]);
+            // the old algorithm always used the end of the path, even with an even number of slides
+            let end = match &objects[i].hit_type {
+                HitType::Slider(slider) => slider.path.end_position(),
+                _ => position,
+            };
+            for j in i + 1..objects.len() {
+                if objects[j].time as f64 - stack_threshold > start_time {
+                    break;
+                }
+
+                if distance(raw_position(&objects[j]), position) < STACK_DISTANCE {
+                    objects[i].stack_height += 1;
+                    start_time = objects[j].time as f64;
+                } else if distance(raw_position(&objects[j]), end) < STACK_DISTANCE {
+                    slider_stack += 1;
+                    objects[j].stack_height -= slider_stack;
+                    start_time = objects[j].time as f64;
+                }
+            }
+        }
+    }
+}
+
+fn is_spinner(hit_object: &HitObject) -> bool {
+    matches!(hit_object.hit_type, HitType::Spinner(_))
+}
+
+fn is_slider(hit_object: &HitObject) -> bool {
+    matches!(hit_object.hit_type, HitType::Slider(_))
+}
+
+fn raw_position(hit_object: &HitObject) -> (f64, f64) {
+    (hit_object.x as f64, hit_object.y as f64)
+}
+
+/// Where the object is when it's over, the slider ball's last position for sliders.
+fn end_position(hit_object: &HitObject) -> (f64, f64) {
+    match &hit_object.hit_type {
+        HitType::Slider(slider) => {
+            slider.position_at(hit_object.end_time() - hit_object.time as f64)
+        }
+        _ => raw_position(hit_object),
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map where sliders move 140 osu! pixels a beat of 500 ms and the stack threshold is
+    /// 420 ms (AR 9, the default leniency of 0.7).
+    fn map(version: u32, hit_objects: &str) -> OsuMap {
+        format!(
+            "osu file format v{}
+
+[Difficulty]
+CircleSize:4
+ApproachRate:9
+SliderMultiplier:1.4
+
+[TimingPoints]
+0,500,4,2,0,100,1,0
+
+[HitObjects]
+{}",
+            version, hit_objects
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn stack_heights(map: &OsuMap) -> Vec<i32> {
+        map.hit_objects
+            .iter()
+            .map(|hit_object| hit_object.stack_height)
+            .collect()
+    }
+
+    #[test]
+    fn stacks_circles_on_each_other() {
+        let map = map(
+            14,
+            "100,100,1000,1,0\n100,100,1100,1,0\n100,100,1200,1,0\n100,100,2000,1,0",
+        );
+        // the last one is too late to stack on the others
+        assert_eq!(stack_heights(&map), [2, 1, 0, 0]);
+
+        let offset = map.difficulty.circle_radius() / 10.0;
+        let (x, y) = map.hit_objects[0].stacked_position;
+        assert!((x - (100.0 - 2.0 * offset)).abs() < 1e-9);
+        assert!((y - (100.0 - 2.0 * offset)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stacks_circles_on_slider_ends_down_and_right() {
+        // the slider ends at 200,100 after 357 ms
+        let map = map(14, "100,100,1000,2,0,L|200:100,1,100\n200,100,1500,1,0");
+        assert_eq!(stack_heights(&map), [0, -1]);
+
+        let offset = map.difficulty.circle_radius() / 10.0;
+        let (x, y) = map.hit_objects[1].stacked_position;
+        assert!((x - (200.0 + offset)).abs() < 1e-9);
+        assert!((y - (100.0 + offset)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stacks_old_maps_from_the_start_of_each_object() {
+        // the slider goes on until 4214, but the last circle is too long after it starts
+        let map = map(
+            5,
+            "100,100,1000,1,0\n100,100,1100,2,0,L|400:100,3,300\n100,100,2000,1,0",
+        );
+        assert_eq!(stack_heights(&map), [1, 1, 0]);
+    }
+
+    #[test]
+    fn stacks_old_maps_on_slider_ends() {
+        let map = map(
+            5,
+            "100,100,1000,2,0,L|200:100,1,100\n200,100,1500,1,0\n200,100,1600,1,0",
+        );
+        assert_eq!(stack_heights(&map), [0, -1, -2]);
+    }
+}
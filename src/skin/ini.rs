@@ -0,0 +1,184 @@
+/// Combo colours of the default skin, used when neither the skin nor the map has any.
+pub const DEFAULT_COMBO_COLOURS: [(u8, u8, u8); 4] =
+    [(255, 192, 0), (0, 202, 0), (18, 124, 255), (242, 24, 57)];
+
+/// The settings of a `skin.ini` the playfield uses.
+///
+/// osu! ignores lines it can't read, so this does too and keeps the defaults for them.
+#[derive(Debug, Clone)]
+pub struct SkinIni {
+    pub(crate) name: String,
+    pub(crate) author: String,
+    /// Whether the cursor grows while a key is held.
+    pub(crate) cursor_expand: bool,
+    /// Whether the cursor image is centred on the cursor, or hangs from its top left corner.
+    pub(crate) cursor_centre: bool,
+    pub(crate) cursor_rotate: bool,
+    pub(crate) combo_colours: Vec<(u8, u8, u8)>,
+    pub(crate) slider_border: Option<(u8, u8, u8)>,
+    pub(crate) slider_track_override: Option<(u8, u8, u8)>,
+    /// Prefix of the number images on hit circles.
+    pub(crate) hit_circle_prefix: String,
+    /// How many pixels the number images overlap, negative to space them out.
+    pub(crate) hit_circle_overlap: i32,
+}
+
+impl Default for SkinIni {
+    fn default() -> Self {
+        SkinIni {
+            name: String::new(),
+            author: String::new(),
+            cursor_expand: true,
+            cursor_centre: true,
+            cursor_rotate: true,
+            combo_colours: DEFAULT_COMBO_COLOURS.to_vec(),
+            slider_border: None,
+            slider_track_override: None,
+            hit_circle_prefix: "default".to_string(),
+            hit_circle_overlap: -2,
+        }
+    }
+}
+
+impl SkinIni {
+    pub fn parse(data: &str) -> Self {
+        let mut ini = SkinIni::default();
+        let mut combo_colours = Vec::new();
+        let mut section = "";
+
+        for line in data.lines() {
+            let line = line.trim().trim_start_matches('\u{feff}');
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = &line[1..line.len() - 1];
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            // values can have trailing comments
+            let value = value.split("//").next().unwrap_or_default().trim();
+
+            match (section, key.trim()) {
+                ("General", "Name") => ini.name = value.to_string(),
+                ("General", "Author") => ini.author = value.to_string(),
+                ("General", "CursorExpand") => set_flag(&mut ini.cursor_expand, value),
+                ("General", "CursorCentre") => set_flag(&mut ini.cursor_centre, value),
+                ("General", "CursorRotate") => set_flag(&mut ini.cursor_rotate, value),
+                ("Colours", "SliderBorder") => ini.slider_border = parse_colour(value),
+                ("Colours", "SliderTrackOverride") => {
+                    ini.slider_track_override = parse_colour(value)
+                }
+                ("Colours", key) if key.starts_with("Combo") => {
+                    if let (Ok(number), Some(colour)) =
+                        (key["Combo".len()..].parse::<u32>(), parse_colour(value))
+                    {
+                        combo_colours.push((number, colour));
+                    }
+                }
+                ("Fonts", "HitCirclePrefix") => ini.hit_circle_prefix = value.replace('\\', "/"),
+                ("Fonts", "HitCircleOverlap") => {
+                    if let Ok(overlap) = value.parse() {
+                        ini.hit_circle_overlap = overlap;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !combo_colours.is_empty() {
+            combo_colours.sort_by_key(|(number, _)| *number);
+            ini.combo_colours = combo_colours
+                .into_iter()
+                .map(|(_, colour)| colour)
+                .collect();
+        }
+        ini
+    }
+}
+
+fn set_flag(flag: &mut bool, value: &str) {
+    match value {
+        "0" => *flag = false,
+        "1" => *flag = true,
+        _ => {}
+    }
+}
+
+/// Parses `r,g,b`, ignoring the alpha some skins add.
+fn parse_colour(value: &str) -> Option<(u8, u8, u8)> {
+    let mut parts = value.split(',').map(|part| part.trim().parse::<u8>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_trailing_comments() {
+        let ini = SkinIni::parse(
+            "[General]
+Name: My Skin // the best one
+// CursorExpand: 0
+CursorRotate: 0 // spinning makes me dizzy
+
+[Fonts]
+HitCirclePrefix: Numbers\\default // in a folder",
+        );
+        assert_eq!(ini.name, "My Skin");
+        assert!(ini.cursor_expand);
+        assert!(!ini.cursor_rotate);
+        assert_eq!(ini.hit_circle_prefix, "Numbers/default");
+    }
+
+    #[test]
+    fn orders_combo_colours_by_number() {
+        let ini = SkinIni::parse(
+            "[Colours]
+Combo3: 0,0,255
+Combo1: 255,0,0
+Combo2: 0,255,0",
+        );
+        assert_eq!(ini.combo_colours, [(255, 0, 0), (0, 255, 0), (0, 0, 255)]);
+    }
+
+    #[test]
+    fn drops_the_alpha_of_colours() {
+        let ini = SkinIni::parse(
+            "[Colours]
+Combo1: 255,128,0,255
+SliderBorder: 10, 20, 30, 128",
+        );
+        assert_eq!(ini.combo_colours, [(255, 128, 0)]);
+        assert_eq!(ini.slider_border, Some((10, 20, 30)));
+    }
+
+    #[test]
+    fn keeps_defaults_for_bad_values() {
+        let ini = SkinIni::parse(
+            "[General]
+CursorCentre: yes
+CursorExpand
+
+[Colours]
+Combo1: 300,0,0
+ComboX: 1,2,3
+SliderTrackOverride: red
+
+[Fonts]
+HitCircleOverlap: lots",
+        );
+        let defaults = SkinIni::default();
+        assert_eq!(ini.cursor_centre, defaults.cursor_centre);
+        assert_eq!(ini.cursor_expand, defaults.cursor_expand);
+        assert_eq!(ini.combo_colours, DEFAULT_COMBO_COLOURS);
+        assert_eq!(ini.slider_track_override, None);
+        assert_eq!(ini.hit_circle_overlap, defaults.hit_circle_overlap);
+    }
+}
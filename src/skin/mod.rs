@@ -0,0 +1,166 @@
+pub mod ini;
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::models::osu_map::{HitObject, OsuMap};
+
+use ini::SkinIni;
+
+/// An image of the skin, with its size in osu! pixels at 1x.
+pub struct SkinTexture {
+    pub(crate) handle: egui::TextureHandle,
+    pub(crate) size: egui::Vec2,
+}
+
+/// An osu! skin folder: `skin.ini` and the images the playfield is drawn with.
+///
+/// Images the skin doesn't have are `None` and drawn with plain shapes instead.
+#[derive(Default)]
+pub struct Skin {
+    pub(crate) directory: Option<PathBuf>,
+    pub(crate) ini: SkinIni,
+    pub(crate) hit_circle: Option<SkinTexture>,
+    pub(crate) hit_circle_overlay: Option<SkinTexture>,
+    pub(crate) approach_circle: Option<SkinTexture>,
+    pub(crate) cursor: Option<SkinTexture>,
+    pub(crate) slider_ball: Option<SkinTexture>,
+    pub(crate) slider_follow_circle: Option<SkinTexture>,
+    pub(crate) spinner_circle: Option<SkinTexture>,
+    pub(crate) spinner_approach_circle: Option<SkinTexture>,
+    /// Hit circle numbers 0 to 9, empty unless the skin has all of them.
+    pub(crate) numbers: Vec<SkinTexture>,
+}
+
+#[derive(Debug)]
+pub enum SkinError {
+    NotFound(PathBuf),
+    Io(io::Error),
+}
+
+impl Display for SkinError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SkinError::NotFound(path) => write!(f, "Skin folder {} not found", path.display()),
+            SkinError::Io(e) => write!(f, "Failed to read skin: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SkinError {}
+
+impl From<io::Error> for SkinError {
+    fn from(e: io::Error) -> Self {
+        SkinError::Io(e)
+    }
+}
+
+impl Skin {
+    pub fn load(ctx: &egui::Context, directory: &Path) -> Result<Self, SkinError> {
+        if !directory.is_dir() {
+            return Err(SkinError::NotFound(directory.to_path_buf()));
+        }
+
+        let ini = match fs::read(directory.join("skin.ini")) {
+            Ok(data) => SkinIni::parse(&String::from_utf8_lossy(&data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => SkinIni::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let texture = |name: &str| load_texture(ctx, directory, name);
+        let slider_ball = texture("sliderb0").or_else(|| texture("sliderb"));
+        let numbers: Vec<SkinTexture> = (0..10)
+            .map_while(|digit| texture(&format!("{}-{}", ini.hit_circle_prefix, digit)))
+            .collect();
+
+        Ok(Skin {
+            directory: Some(directory.to_path_buf()),
+            hit_circle: texture("hitcircle"),
+            hit_circle_overlay: texture("hitcircleoverlay"),
+            approach_circle: texture("approachcircle"),
+            cursor: texture("cursor"),
+            slider_ball,
+            slider_follow_circle: texture("sliderfollowcircle"),
+            spinner_circle: texture("spinner-circle"),
+            spinner_approach_circle: texture("spinner-approachcircle"),
+            numbers: if numbers.len() == 10 {
+                numbers
+            } else {
+                Vec::new()
+            },
+            ini,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        if self.ini.name.is_empty() {
+            "Default"
+        } else {
+            &self.ini.name
+        }
+    }
+
+    /// Combo colour of `hit_object`. The map's colours win over the skin's.
+    pub fn combo_colour(&self, beatmap: &OsuMap, hit_object: &HitObject) -> (u8, u8, u8) {
+        let colours = if beatmap.colours.combo_colours.is_empty() {
+            &self.ini.combo_colours
+        } else {
+            &beatmap.colours.combo_colours
+        };
+        colours
+            .get(hit_object.combo_colour_index % colours.len().max(1))
+            .copied()
+            .unwrap_or(ini::DEFAULT_COMBO_COLOURS[0])
+    }
+
+    pub fn slider_border(&self, beatmap: &OsuMap) -> Option<(u8, u8, u8)> {
+        beatmap.colours.slider_border.or(self.ini.slider_border)
+    }
+
+    pub fn slider_track_override(&self, beatmap: &OsuMap) -> Option<(u8, u8, u8)> {
+        beatmap
+            .colours
+            .slider_track_override
+            .or(self.ini.slider_track_override)
+    }
+}
+
+/// The skin the player uses, as set in the `osu!.<user>.cfg` next to `osu!.db`.
+pub fn find_user_skin(osu_directory: &Path) -> Option<PathBuf> {
+    let config = fs::read_dir(osu_directory).ok()?.flatten().find(|entry| {
+        let name = entry.file_name().to_string_lossy().to_string();
+        name.starts_with("osu!.") && name.ends_with(".cfg") && name != "osu!.cfg"
+    })?;
+    let config = fs::read_to_string(config.path()).ok()?;
+    let skin = config.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "Skin").then(|| value.trim().to_string())
+    })?;
+
+    let directory = osu_directory.join("Skins").join(skin);
+    directory.is_dir().then_some(directory)
+}
+
+/// Loads `name@2x.png` at half its size, or else `name.png`.
+fn load_texture(ctx: &egui::Context, directory: &Path, name: &str) -> Option<SkinTexture> {
+    [
+        (format!("{}@2x.png", name), 0.5),
+        (format!("{}.png", name), 1.0),
+    ]
+    .into_iter()
+    .find_map(|(file_name, scale)| {
+        let image = image::open(directory.join(&file_name)).ok()?.to_rgba8();
+        let (width, height) = image.dimensions();
+        let image = egui::ColorImage::from_rgba_unmultiplied(
+            [width as usize, height as usize],
+            image.as_raw(),
+        );
+        Some(SkinTexture {
+            handle: ctx.load_texture(file_name, image, egui::TextureOptions::LINEAR),
+            size: egui::Vec2::new(width as f32, height as f32) * scale,
+        })
+    })
+}